@@ -0,0 +1,67 @@
+//! Compare peak heap usage of `handle_request` and `handle_large_request`
+//! for a request carrying large params. Run with `cargo bench`.
+extern crate jsonrpc;
+extern crate rustc_serialize;
+
+use jsonrpc::{HashMapWithMethods, JsonRpcServer};
+use rustc_serialize::json::{Json, ToJson};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/**
+ * Peak bytes allocated on top of already live allocations while running `f`.
+ * */
+fn peak_during<F: FnOnce()>(f: F) -> usize {
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    f();
+    PEAK.load(Ordering::SeqCst) - base
+}
+
+fn main() {
+    let mut handler = HashMapWithMethods::new();
+    handler.insert("count".to_owned(),
+                   Box::new(|req| Ok(req.params.and_then(Json::as_array).map_or(0, Vec::len).to_json())));
+    let server = JsonRpcServer::new_handler(handler);
+
+    let params = vec!["0123456789abcdef".to_owned(); 100_000].to_json().to_string();
+    let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"params\": {}, \"id\": 1}}",
+                          params)
+        .into_bytes();
+
+    // Both paths start from a reader, as requests arrive from transport
+    let buffered = peak_during(|| {
+        let mut text = String::new();
+        (&request[..]).read_to_string(&mut text).unwrap();
+        server.handle_request(&text).unwrap();
+    });
+    let streamed = peak_during(|| {
+        server.handle_large_request(&request[..], &()).unwrap().unwrap();
+    });
+
+    println!("request size:         {:>10} bytes", request.len());
+    println!("handle_request:       {:>10} bytes peak", buffered);
+    println!("handle_large_request: {:>10} bytes peak", streamed);
+}
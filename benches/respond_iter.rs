@@ -0,0 +1,38 @@
+//! Compare building large array results with `respond_iter` and by collecting
+//! into `Vec<Json>` first. Run with `cargo bench`.
+extern crate jsonrpc;
+extern crate rustc_serialize;
+
+use rustc_serialize::json::{Json, ToJson};
+use std::time::{Duration, Instant};
+
+const ITEMS: usize = 1_000_000;
+const ROUNDS: u32 = 10;
+
+/**
+ * Average time of building result with `f`.
+ * */
+fn measure<F: Fn() -> Json>(f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let result = f();
+        assert_eq!(Some(ITEMS), result.as_array().map(Vec::len));
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    // Filter hides length of sequence from iterator
+    let items = || (0..ITEMS as u64 * 2).filter(|x| x % 2 == 0);
+
+    let collected = measure(|| {
+        let mut array = Vec::new();
+        array.extend(items().map(|x| x.to_json()));
+        Json::Array(array)
+    });
+    let reserved = measure(|| jsonrpc::respond_iter(ITEMS, items()).unwrap());
+
+    println!("items:        {:>10}", ITEMS);
+    println!("collect:      {:>10?}", collected);
+    println!("respond_iter: {:>10?}", reserved);
+}
@@ -0,0 +1,342 @@
+//! Assembling batch responses.
+use rustc_serialize::json::Json;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Mutex;
+use std::thread;
+
+use {Handler, JsonRpcServer};
+
+/**
+ * Builder of batch response from responses to individual requests, eg. answered by
+ * different workers. Responses are kept in order of `push`, notifications are omitted.
+ * */
+#[derive(Debug, Default)]
+pub struct ResponseBatch {
+    responses: Vec<Json>,
+}
+
+impl ResponseBatch {
+    /**
+     * Create empty batch.
+     * */
+    pub fn new() -> ResponseBatch {
+        Default::default()
+    }
+
+    /**
+     * Create empty batch with space for given number of responses.
+     * */
+    pub fn with_capacity(capacity: usize) -> ResponseBatch {
+        ResponseBatch { responses: Vec::with_capacity(capacity) }
+    }
+
+    /**
+     * Append response to next request. None marks notification, which has no response.
+     * */
+    pub fn push(&mut self, response: Option<Json>) {
+        if let Some(response) = response {
+            self.responses.push(response);
+        }
+    }
+
+    /**
+     * Get batch response. None if batch contained only notifications.
+     * */
+    pub fn finish(self) -> Option<Json> {
+        if self.responses.is_empty() {
+            None
+        } else {
+            Some(Json::Array(self.responses))
+        }
+    }
+
+    /**
+     * Build batch response from responses tagged with index of request, in any order.
+     * None if batch contained only notifications.
+     * */
+    pub fn from_indexed(mut responses: Vec<(usize, Option<Json>)>) -> Option<Json> {
+        responses.sort_by_key(|&(index, _)| index);
+        let mut batch = ResponseBatch::with_capacity(responses.len());
+        for (_, response) in responses {
+            batch.push(response);
+        }
+        batch.finish()
+    }
+}
+
+/**
+ * Processing of single batch element.
+ * */
+pub type BatchJob<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/**
+ * Executor running batch elements, eg. on thread pool. All jobs must be finished before
+ * `execute_all` returns, in any order.
+ * */
+pub trait BatchExecutor {
+    /**
+     * Run all jobs to completion.
+     * */
+    fn execute_all<'a>(&self, jobs: Vec<BatchJob<'a>>);
+}
+
+/**
+ * Executor spreading jobs over given number of scoped threads, spawned for each batch.
+ * */
+#[derive(Debug, Clone, Copy)]
+pub struct ScopedThreads {
+    threads: usize,
+}
+
+impl ScopedThreads {
+    /**
+     * Create executor using at most `threads` threads (at least one).
+     * */
+    pub fn new(threads: usize) -> ScopedThreads {
+        ScopedThreads { threads: threads.max(1) }
+    }
+}
+
+impl BatchExecutor for ScopedThreads {
+    fn execute_all<'a>(&self, jobs: Vec<BatchJob<'a>>) {
+        let mut queues: Vec<Vec<BatchJob<'a>>> = (0..self.threads.min(jobs.len())).map(|_| Vec::new()).collect();
+        let count = queues.len();
+        for (index, job) in jobs.into_iter().enumerate() {
+            queues[index % count].push(job);
+        }
+        thread::scope(|scope| {
+            for queue in queues {
+                scope.spawn(move || queue.into_iter().for_each(|job| job()));
+            }
+        });
+    }
+}
+
+/**
+ * Key under which responses with null id are kept in batch response map.
+ * */
+pub const NULL_ID_KEY: &str = "null";
+
+/**
+ * Insert response into batch response map. When key is already taken (e.g. ids `1` and `"1"`),
+ * responses sharing it are collected in array, so none of them is lost.
+ * */
+fn insert_response(map: &mut BTreeMap<String, Json>, key: String, response: Json) {
+    match map.entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(response);
+        }
+        Entry::Occupied(mut entry) => {
+            match *entry.get_mut() {
+                Json::Array(ref mut responses) => responses.push(response),
+                ref mut single => {
+                    let first = mem::replace(single, Json::Null);
+                    *single = Json::Array(vec![first, response]);
+                }
+            }
+        }
+    }
+}
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Non-standard variant of `handle_request_context` for clients looking up responses by id.
+     * Batch response is an object mapping id (string ids as is, others serialized) to response.
+     * Responses with null id are collected in array under `NULL_ID_KEY`. Responses whose ids map
+     * to the same key (e.g. `1` and `"1"`, or `"null"` and null) are collected in array as well;
+     * single response is always an object, so arrays mark such collisions.
+     * Response to single request is returned unchanged.
+     * */
+    pub fn handle_request_map(&self, request: &str, custom: &H::Context) -> Option<Json> {
+        let result = self._handle_request(request, custom);
+        match self._response_json(result)? {
+            Json::Array(responses) => {
+                let mut map = BTreeMap::new();
+                let mut without_id = Vec::new();
+                for response in responses {
+                    let key = match response.find("id") {
+                        Some(Json::String(id)) => id.clone(),
+                        Some(Json::Null) | None => {
+                            without_id.push(response);
+                            continue;
+                        }
+                        Some(id) => id.to_string(),
+                    };
+                    insert_response(&mut map, key, response);
+                }
+                if !without_id.is_empty() {
+                    let mut responses = match map.remove(NULL_ID_KEY) {
+                        Some(Json::Array(responses)) => responses,
+                        Some(response) => vec![response],
+                        None => Vec::new(),
+                    };
+                    responses.extend(without_id);
+                    map.insert(NULL_ID_KEY.to_owned(), Json::Array(responses));
+                }
+                Some(Json::Object(map))
+            }
+            response => Some(response),
+        }
+    }
+
+    /**
+     * Process element of batch with given index. May be called concurrently for all elements,
+     * results are assembled with `ResponseBatch::from_indexed`.
+     * */
+    pub fn handle_batch_element(&self, index: usize, request: &Json, custom: &H::Context) -> (usize, Option<Json>) {
+        (index, self._handle_element(request, custom))
+    }
+
+    /**
+     * Process batch with elements dispatched on executor. Response is the same as from
+     * sequential processing: in order of requests, without notifications.
+     * */
+    pub fn handle_batch_parallel<E>(&self, batch: &[Json], custom: &H::Context, executor: &E) -> Option<Json>
+        where E: BatchExecutor,
+              H: Sync,
+              H::Context: Sync
+    {
+        if let Err(e) = self._check_batch(batch.len()) {
+            return self._response_json(Err(e));
+        }
+        let responses = Mutex::new(Vec::with_capacity(batch.len()));
+        let jobs = batch.iter()
+                        .enumerate()
+                        .map(|(index, request)| {
+                            let responses = &responses;
+                            Box::new(move || {
+                                let response = self.handle_batch_element(index, request, custom);
+                                responses.lock().unwrap().push(response);
+                            }) as BatchJob
+                        })
+                        .collect();
+        executor.execute_all(jobs);
+        ResponseBatch::from_indexed(responses.into_inner().unwrap()).map(|response| self._versioned(response))
+    }
+
+    /**
+     * Process all elements of batch, tagging responses with index of request.
+     * Unlike `handle_request` empty or oversized batches are not rejected.
+     * */
+    pub fn handle_batch_indexed(&self, batch: &[Json], custom: &H::Context) -> Vec<(usize, Option<Json>)> {
+        batch.iter().enumerate().map(|(index, request)| self.handle_batch_element(index, request, custom)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::ToJson;
+    use {HashMapWithMethods, JsonRpcServerBuilder};
+
+    #[test]
+    fn test_response_batch() {
+        let mut batch = ResponseBatch::new();
+        batch.push(Some(Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}").unwrap()));
+        batch.push(None);
+        batch.push(Some(Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 2}").unwrap()));
+        let expected = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}, \
+                                       {\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 2}]");
+        assert_eq!(expected.ok(), batch.finish());
+    }
+
+    #[test]
+    fn test_only_notifications() {
+        let mut batch = ResponseBatch::new();
+        batch.push(None);
+        batch.push(None);
+        assert_eq!(None, batch.finish());
+    }
+
+    #[test]
+    fn test_indexed_batch() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("echo".to_owned(), Box::new(|req| Ok(req.params.cloned().unwrap_or(Json::Null))));
+        let server = JsonRpcServer::new_handler(handler);
+        let requests = Json::from_str("[{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [1], \"id\": 1}, \
+                                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [2]}, \
+                                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [3], \"id\": 3}]")
+                           .unwrap();
+        let mut responses = server.handle_batch_indexed(requests.as_array().unwrap(), &());
+        assert_eq!(vec![0, 1, 2], responses.iter().map(|&(index, _)| index).collect::<Vec<_>>());
+        assert_eq!(None, responses[1].1);
+
+        // Executor may finish elements in any order
+        responses.reverse();
+        responses.swap(0, 1);
+        let expected = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": [1], \"id\": 1}, \
+                                       {\"jsonrpc\": \"2.0\", \"result\": [3], \"id\": 3}]")
+                           .unwrap();
+        assert_eq!(expected.to_string(), ResponseBatch::from_indexed(responses).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_request_map() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("echo".to_owned(), Box::new(|req| Ok(req.params.cloned().unwrap_or(Json::Null))));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [1], \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [2], \"id\": \"b\"}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [3]}, 5]";
+        let expected = Json::from_str("{\"1\": {\"jsonrpc\": \"2.0\", \"result\": [1], \"id\": 1}, \
+                                       \"b\": {\"jsonrpc\": \"2.0\", \"result\": [2], \"id\": \"b\"}, \
+                                       \"null\": [{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \
+                                       \"message\": \"Invalid Request\"}, \"id\": null}]}")
+                           .unwrap();
+        assert_eq!(expected.to_string(), server.handle_request_map(request, &()).unwrap().to_string());
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [1], \"id\": 1}";
+        let expected = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": [1], \"id\": 1}").unwrap();
+        assert_eq!(expected.to_string(), server.handle_request_map(request, &()).unwrap().to_string());
+
+        // Colliding ids keep all responses
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [1], \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [2], \"id\": \"1\"}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [3], \"id\": \"null\"}, 5]";
+        let expected = Json::from_str("{\"1\": [{\"jsonrpc\": \"2.0\", \"result\": [1], \"id\": 1}, \
+                                       {\"jsonrpc\": \"2.0\", \"result\": [2], \"id\": \"1\"}], \
+                                       \"null\": [{\"jsonrpc\": \"2.0\", \"result\": [3], \"id\": \"null\"}, \
+                                       {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \
+                                       \"message\": \"Invalid Request\"}, \"id\": null}]}")
+                           .unwrap();
+        assert_eq!(expected.to_string(), server.handle_request_map(request, &()).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parallel_batch() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("echo".to_owned(), Box::new(|req| Ok(req.params.cloned().unwrap_or(Json::Null))));
+        let server = JsonRpcServer::new_handler(handler);
+        let element = |i: usize| match i % 4 {
+            0 => format!("{{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [{}]}}", i),
+            1 => format!("{{\"jsonrpc\": \"2.0\", \"method\": \"missing\", \"id\": {}}}", i),
+            2 => i.to_string(),
+            _ => format!("{{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [{}], \"id\": {}}}", i, i),
+        };
+        let elements: Vec<String> = (0..20).map(element).collect();
+        let request = format!("[{}]", elements.join(", "));
+        let sequential = server.handle_request(&request).unwrap();
+        let batch = Json::from_str(&request).unwrap();
+        let parallel = server.handle_batch_parallel(batch.as_array().unwrap(), &(), &ScopedThreads::new(4)).unwrap();
+        assert_eq!(sequential, parallel.to_string());
+
+        let empty = server.handle_batch_parallel(&[], &(), &ScopedThreads::new(4)).unwrap();
+        assert_eq!(Some(-32600), empty.find_path(&["error", "code"]).and_then(Json::as_i64));
+    }
+
+    #[test]
+    fn test_parallel_batch_without_version() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("one".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServerBuilder::new(handler).emit_version_field(false).build();
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"one\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"two\", \"id\": 2}]";
+        let sequential = server.handle_request(request).unwrap();
+        let batch = Json::from_str(request).unwrap();
+        let parallel = server.handle_batch_parallel(batch.as_array().unwrap(), &(), &ScopedThreads::new(2)).unwrap();
+        assert_eq!(sequential, parallel.to_string());
+        assert!(parallel.as_array().unwrap().iter().all(|response| response.find("jsonrpc").is_none()));
+    }
+}
@@ -0,0 +1,39 @@
+//! Convention for binary payloads: bytes travel as a single JSON string holding standard
+//! base64 (RFC 4648 alphabet, with `=` padding, no line breaks).
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+use rustc_serialize::json::Json;
+
+use {ErrorJsonRpc, ResponseParseError};
+
+/**
+ * Encode bytes as method result. Returns `Result` so method can end with `respond_bytes(&data)`.
+ * */
+pub fn respond_bytes(bytes: &[u8]) -> Result<Json, ErrorJsonRpc> {
+    Ok(Json::String(bytes.to_base64(STANDARD)))
+}
+
+/**
+ * Decode result produced by `respond_bytes`.
+ * */
+pub fn decode_bytes(result: &Json) -> Result<Vec<u8>, ResponseParseError> {
+    let encoded = result.as_string().ok_or(ResponseParseError::InvalidResponse("binary result must be string"))?;
+    encoded.from_base64().map_err(|_| ResponseParseError::InvalidResponse("binary result is not valid base64"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+
+    use super::*;
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let bytes = [0u8, 1, 2, 254, 255, b'x'];
+        let result = respond_bytes(&bytes).unwrap();
+        assert_eq!(Json::String("AAEC/v94".to_owned()), result);
+        assert_eq!(bytes.to_vec(), decode_bytes(&result).unwrap());
+
+        assert!(decode_bytes(&Json::U64(1)).is_err());
+        assert!(decode_bytes(&Json::String("not base64!".to_owned())).is_err());
+    }
+}
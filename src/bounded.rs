@@ -0,0 +1,165 @@
+//! Rejecting requests when too many are already being processed.
+use rustc_serialize::json::{Json, ToJson};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {Deprecation, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest, StreamingHandlerResult};
+
+/**
+ * Handler allowing limited number of concurrent calls. Calls over the limit are rejected
+ * immediately with `ServerError(-32000, "server busy")` instead of waiting.
+ * */
+pub struct BoundedHandler<H> {
+    inner: H,
+    permits: usize,
+    in_flight: AtomicUsize,
+}
+
+/**
+ * Slot of running call, released when dropped (also on panic).
+ * */
+struct Permit<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<H> BoundedHandler<H> {
+    /**
+     * Wrap handler, allowing at most `permits` concurrent calls.
+     * */
+    pub fn new(inner: H, permits: usize) -> BoundedHandler<H> {
+        BoundedHandler {
+            inner,
+            permits,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+     * Number of calls being processed right now.
+     * */
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /**
+     * Get wrapped handler reference
+     * */
+    pub fn get_inner(&self) -> &H {
+        &self.inner
+    }
+
+    /**
+     * Get mutable wrapped handler reference
+     * */
+    pub fn get_inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+
+    fn acquire(&self) -> Result<Permit<'_>, usize> {
+        self.in_flight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| if n < self.permits { Some(n + 1) } else { None })
+            .map(|_| Permit(&self.in_flight))
+    }
+
+    /**
+     * Take permit for calling inner handler with request, rejecting it when server is busy.
+     * */
+    fn admit(&self, req: &JsonRpcRequest) -> Result<Permit<'_>, ErrorJsonRpc> {
+        self.acquire().map_err(|depth| {
+            warn!("Server busy, '{}' rejected", req.method);
+            let mut data = BTreeMap::new();
+            data.insert("queueDepth".to_owned(), (depth as u64).to_json());
+            ErrorJsonRpc::new_data(ErrorCode::ServerError(-32000, "server busy"), Json::Object(data))
+        })
+    }
+}
+
+impl<H: Handler> Handler for BoundedHandler<H> {
+    type Context = H::Context;
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        let _permit = self.admit(req)?;
+        self.inner.handle_outcome(req, custom)
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        let _permit = self.admit(req)?;
+        self.inner.handle_streaming(req, custom, sink)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.inner.deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.inner.has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.inner.is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.inner.requires_response(method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use JsonRpcServer;
+
+    struct Blocking {
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl Handler for Blocking {
+        type Context = ();
+        fn handle(&self, _: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+            self.release.lock().unwrap().recv().unwrap();
+            Ok(Json::Null)
+        }
+    }
+
+    #[test]
+    fn test_server_busy() {
+        let (release, receiver) = channel();
+        let handler = BoundedHandler::new(Blocking { release: Mutex::new(receiver) }, 1);
+        let server = Arc::new(JsonRpcServer::new_handler(handler));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"work\", \"id\": 1}";
+
+        let worker = {
+            let server = server.clone();
+            thread::spawn(move || server.handle_request(request))
+        };
+        while server.get_handler().in_flight() == 0 {
+            thread::yield_now();
+        }
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \
+                                                \"message\": \"server busy\", \"data\": {\"queueDepth\": 1}}, \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        release.send(()).unwrap();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": null, \"id\": 1}");
+        let response = Json::from_str(&worker.join().unwrap().unwrap());
+        assert_eq!(expected_response, response);
+        assert_eq!(0, server.get_handler().in_flight());
+    }
+}
@@ -0,0 +1,333 @@
+//! Caching results of idempotent methods.
+use rustc_serialize::json::Json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use {Deprecation, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest, StreamingHandlerResult};
+
+/**
+ * Outcome of call shared with concurrent calls waiting for it.
+ * */
+type Outcome = Result<HandlerResult, ErrorJsonRpc>;
+
+/**
+ * Method name and serialized params.
+ * */
+type Key = (String, String);
+
+/**
+ * Call of wrapped handler in progress, awaited by concurrent calls with the same key.
+ * */
+#[derive(Default)]
+struct InFlight {
+    outcome: Mutex<Option<Outcome>>,
+    done: Condvar,
+}
+
+impl InFlight {
+    /**
+     * Block until call finishes, returning its outcome.
+     * */
+    fn wait(&self) -> Outcome {
+        let mut outcome = self.outcome.lock().unwrap();
+        loop {
+            if let Some(ref outcome) = *outcome {
+                return outcome.clone();
+            }
+            outcome = self.done.wait(outcome).unwrap();
+        }
+    }
+
+    /**
+     * Pass outcome to all waiting calls.
+     * */
+    fn finish(&self, outcome: Outcome) {
+        *self.outcome.lock().unwrap() = Some(outcome);
+        self.done.notify_all();
+    }
+}
+
+enum Slot {
+    InFlight(Arc<InFlight>),
+    Cached(Instant, HandlerResult),
+}
+
+/**
+ * Cached results and calls in progress, with keys of results in order of expiry (which is
+ * order of insertion, as time to live is fixed).
+ * */
+#[derive(Default)]
+struct Entries {
+    slots: HashMap<Key, Slot>,
+    expiry: VecDeque<(Instant, Key)>,
+}
+
+impl Entries {
+    /**
+     * Drop results whose time to live has passed.
+     * */
+    fn prune(&mut self, now: Instant) {
+        while self.expiry.front().is_some_and(|&(expires, _)| expires <= now) {
+            let (expires, key) = self.expiry.pop_front().unwrap();
+            // Key may have been cached again since
+            if let Some(&Slot::Cached(cached, _)) = self.slots.get(&key) {
+                if cached == expires {
+                    self.slots.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Handler reusing results of cacheable methods called again with the same params
+ * within time to live. Only successful results of calls with id are cached.
+ * Concurrent calls missing cache are coalesced: only the first one is passed to wrapped
+ * handler, others wait for it and share its outcome (error included).
+ * */
+pub struct CachingHandler<H> {
+    inner: H,
+    ttl: Duration,
+    cacheable: HashSet<String>,
+    entries: Mutex<Entries>,
+}
+
+impl<H> CachingHandler<H> {
+    /**
+     * Wrap handler. No method is cacheable until marked with `cacheable`.
+     * */
+    pub fn new(inner: H, ttl: Duration) -> CachingHandler<H> {
+        CachingHandler {
+            inner,
+            ttl,
+            cacheable: HashSet::new(),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /**
+     * Mark method as cacheable. Method must not have side effects.
+     * */
+    pub fn cacheable(mut self, method: &str) -> CachingHandler<H> {
+        self.cacheable.insert(method.to_owned());
+        self
+    }
+
+    /**
+     * Drop all cached results. Calls in progress are not affected.
+     * */
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.slots.retain(|_, slot| matches!(*slot, Slot::InFlight(_)));
+        entries.expiry.clear();
+    }
+
+    /**
+     * Get wrapped handler reference
+     * */
+    pub fn get_inner(&self) -> &H {
+        &self.inner
+    }
+
+    /**
+     * Get mutable wrapped handler reference
+     * */
+    pub fn get_inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+}
+
+impl<H: Handler> Handler for CachingHandler<H> {
+    type Context = H::Context;
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        if req.id.is_none() || !self.cacheable.contains(&*req.method) {
+            return self.inner.handle_outcome(req, custom);
+        }
+
+        let key = (req.method.to_string(), req.params.map_or_else(String::new, Json::to_string));
+        let flight = {
+            let mut entries = self.entries.lock().unwrap();
+            let waiting = match entries.slots.get(&key) {
+                Some(&Slot::Cached(expires, ref result)) if Instant::now() < expires => {
+                    debug!("Cached result of '{}' used", req.method);
+                    return Ok(result.clone());
+                }
+                Some(Slot::InFlight(flight)) => Some(flight.clone()),
+                _ => None,
+            };
+            match waiting {
+                Some(flight) => {
+                    drop(entries);
+                    debug!("Waiting for call of '{}' in progress", req.method);
+                    return flight.wait();
+                }
+                None => {
+                    let flight = Arc::new(InFlight::default());
+                    entries.slots.insert(key.clone(), Slot::InFlight(flight.clone()));
+                    flight
+                }
+            }
+        };
+
+        // Lock is not held while calling handler. Waiting calls are released even if it panics.
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| self.inner.handle_outcome(req, custom)));
+        let shared = match outcome {
+            Ok(ref outcome) => outcome.clone(),
+            Err(_) => Err(ErrorJsonRpc::new(ErrorCode::InternalError)),
+        };
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let now = Instant::now();
+            entries.prune(now);
+            match shared {
+                Ok(ref result) => {
+                    let expires = now + self.ttl;
+                    entries.slots.insert(key.clone(), Slot::Cached(expires, result.clone()));
+                    entries.expiry.push_back((expires, key));
+                }
+                Err(_) => {
+                    entries.slots.remove(&key);
+                }
+            }
+        }
+        flight.finish(shared);
+        outcome.unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        if req.id.is_none() || !self.cacheable.contains(&*req.method) {
+            return self.inner.handle_streaming(req, custom, sink);
+        }
+        // Cached result must be whole, so it is collected instead
+        self.handle_outcome(req, custom)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.inner.deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.inner.has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.inner.is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.inner.requires_response(method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::ToJson;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use tests::Counter;
+    use {HashMapWithMethods, JsonRpcServer};
+
+    fn calls(server: &JsonRpcServer<CachingHandler<Counter>>) -> usize {
+        server.get_handler().get_inner().calls.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_cached_result() {
+        let handler = CachingHandler::new(Counter::default(), Duration::from_secs(60)).cacheable("get");
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"get\", \"params\": [1], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        for _ in 0..2 {
+            let response = Json::from_str(&server.handle_request(request).unwrap());
+            assert_eq!(expected_response, response);
+        }
+        assert_eq!(1, calls(&server));
+
+        // Different params are cached separately
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"get\", \"params\": [2], \"id\": 1}";
+        server.handle_request(request);
+        assert_eq!(2, calls(&server));
+    }
+
+    #[test]
+    fn test_not_cached() {
+        let handler = CachingHandler::new(Counter::default(), Duration::from_secs(60))
+            .cacheable("get")
+            .cacheable("fail");
+        let server = JsonRpcServer::new_handler(handler);
+        for request in &["{\"jsonrpc\": \"2.0\", \"method\": \"other\", \"id\": 1}",
+                         "{\"jsonrpc\": \"2.0\", \"method\": \"fail\", \"id\": 1}",
+                         "{\"jsonrpc\": \"2.0\", \"method\": \"get\"}"] {
+            let before = calls(&server);
+            server.handle_request(request);
+            server.handle_request(request);
+            assert_eq!(before + 2, calls(&server), "{}", request);
+        }
+    }
+
+    #[test]
+    fn test_expired() {
+        let handler = CachingHandler::new(Counter::default(), Duration::from_secs(0)).cacheable("get");
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"get\", \"id\": 1}";
+        server.handle_request(request);
+        server.handle_request(request);
+        assert_eq!(2, calls(&server));
+    }
+
+    #[test]
+    fn test_coalesced() {
+        let (release, receiver) = channel();
+        let receiver = Mutex::new(receiver);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut methods = HashMapWithMethods::new();
+        methods.insert("get".to_owned(),
+                       Box::new(move |_| {
+                           receiver.lock().unwrap().recv().unwrap();
+                           Ok((counter.fetch_add(1, Ordering::SeqCst) + 1).to_json())
+                       }));
+        let handler = CachingHandler::new(methods, Duration::from_secs(60)).cacheable("get");
+        let server = Arc::new(JsonRpcServer::new_handler(handler));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"get\", \"id\": 1}";
+        let callers: Vec<_> = (0..4).map(|_| {
+                                        let server = server.clone();
+                                        thread::spawn(move || server.handle_request(request))
+                                    })
+                                    .collect();
+
+        // Let all callers miss cache before handler finishes
+        thread::sleep(Duration::from_millis(100));
+        for _ in 0..4 {
+            release.send(()).unwrap();
+        }
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        for caller in callers {
+            assert_eq!(expected_response, Json::from_str(&caller.join().unwrap().unwrap()));
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_expired_pruned() {
+        let handler = CachingHandler::new(Counter::default(), Duration::from_secs(0)).cacheable("get");
+        let server = JsonRpcServer::new_handler(handler);
+        for i in 0..100 {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"get\", \"params\": [{}], \"id\": 1}}", i);
+            server.handle_request(&request);
+        }
+        assert_eq!(1, server.get_handler().entries.lock().unwrap().slots.len());
+    }
+}
@@ -0,0 +1,104 @@
+//! Describing error codes which may be returned by server.
+use rustc_serialize::json::{Json, ToJson};
+use std::collections::BTreeMap;
+
+use {ErrorCode, Handler, JsonRpcServer};
+
+/**
+ * Error codes defined by specification.
+ * */
+const RESERVED: [ErrorCode; 5] = [ErrorCode::ParseError,
+                                  ErrorCode::InvalidRequest,
+                                  ErrorCode::MethodNotFound,
+                                  ErrorCode::InvalidParams,
+                                  ErrorCode::InternalError];
+
+/**
+ * Application defined errors which may be returned by methods, published in error catalog.
+ * */
+#[derive(Debug, Clone, Default)]
+pub struct ErrorRegistry {
+    errors: BTreeMap<i32, String>,
+}
+
+impl ErrorRegistry {
+    /**
+     * Create registry without errors.
+     * */
+    pub fn new() -> ErrorRegistry {
+        Default::default()
+    }
+
+    /**
+     * Register error code with its message, replacing previous message of that code.
+     * Codes defined by specification are always in catalog, so they are ignored with warning.
+     * */
+    pub fn register(&mut self, code: i32, message: &str) -> &mut ErrorRegistry {
+        if RESERVED.iter().any(|e| e.get_code() == code) {
+            warn!("Error code {} is reserved by specification, not registering it", code);
+            return self;
+        }
+        self.errors.insert(code, message.to_owned());
+        self
+    }
+}
+
+/**
+ * Catalog entry of single error.
+ * */
+fn entry(code: i32, message: &str, reserved: bool) -> Json {
+    let mut d = BTreeMap::new();
+    d.insert("code".to_owned(), code.to_json());
+    d.insert("message".to_owned(), message.to_json());
+    d.insert("reserved".to_owned(), reserved.to_json());
+    Json::Object(d)
+}
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Describe known errors as array of objects with `code`, `message` and `reserved` flag
+     * (true for errors defined by specification), eg. for generating client SDKs.
+     * Application errors come from registry set with `set_error_registry`.
+     * */
+    pub fn error_catalog(&self) -> Json {
+        let reserved = RESERVED.iter().map(|e| entry(e.get_code(), e.get_desc(), true));
+        let registered = self.error_registry.errors.iter().map(|(&code, message)| entry(code, message, false));
+        Json::Array(reserved.chain(registered).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_catalog() {
+        let mut registry = ErrorRegistry::new();
+        registry.register(1001, "Insufficient funds").register(-32010, "Account locked");
+        let mut server = JsonRpcServer::new();
+        server.set_error_registry(registry);
+
+        let catalog = server.error_catalog();
+        let entries = catalog.as_array().unwrap();
+        assert_eq!(7, entries.len());
+        for &(code, message) in &[(-32700, "Parse error"), (-32600, "Invalid Request"), (-32601, "Method not found"),
+                                  (-32602, "Invalid params"), (-32603, "Internal error")] {
+            assert!(entries.contains(&entry(code, message, true)), "{}", code);
+        }
+        assert_eq!(&entry(-32010, "Account locked", false), &entries[5]);
+        assert_eq!(&entry(1001, "Insufficient funds", false), &entries[6]);
+    }
+
+    #[test]
+    fn test_reserved_not_registered() {
+        let mut registry = ErrorRegistry::new();
+        registry.register(-32601, "No such method").register(1001, "Insufficient funds");
+        let mut server = JsonRpcServer::new();
+        server.set_error_registry(registry);
+
+        let catalog = server.error_catalog();
+        let entries = catalog.as_array().unwrap();
+        assert_eq!(6, entries.len());
+        assert_eq!(1, entries.iter().filter(|e| e.find("code") == Some(&(-32601).to_json())).count());
+    }
+}
@@ -0,0 +1,609 @@
+//! Client side helpers for consuming JSON-RPC responses.
+use rustc_serialize::json::{Decoder, DecoderError, Json, Object, ParserError, ToJson};
+use rustc_serialize::Decodable;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use {ErrorCode, ErrorJsonRpc};
+
+/**
+ * Generator of unique numeric request ids. Safe to share between threads.
+ * */
+#[derive(Debug)]
+pub struct IdGenerator {
+    next: AtomicU64,
+}
+
+impl IdGenerator {
+    /**
+     * Create generator producing ids starting from 1.
+     * */
+    pub fn new() -> IdGenerator {
+        IdGenerator::starting_at(1)
+    }
+
+    /**
+     * Create generator producing ids starting from given value.
+     * */
+    pub fn starting_at(first: u64) -> IdGenerator {
+        IdGenerator { next: AtomicU64::new(first) }
+    }
+
+    /**
+     * Get next id.
+     * */
+    pub fn next(&self) -> Json {
+        Json::U64(self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for IdGenerator {
+    fn default() -> IdGenerator {
+        IdGenerator::new()
+    }
+}
+
+/**
+ * Request built by client.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcClientRequest {
+    method: String,
+    params: Option<Json>,
+    id: Option<Json>,
+}
+
+impl JsonRpcClientRequest {
+    /**
+     * Create notification calling given method without parameters.
+     * */
+    pub fn new(method: &str) -> JsonRpcClientRequest {
+        JsonRpcClientRequest {
+            method: method.to_owned(),
+            params: None,
+            id: None,
+        }
+    }
+
+    /**
+     * Set parameters. Only Array (by position) or Object (by name) should be used.
+     * */
+    pub fn with_params(mut self, params: Json) -> JsonRpcClientRequest {
+        self.params = Some(params);
+        self
+    }
+
+    /**
+     * Set request id, turning notification into call.
+     * */
+    pub fn with_id(mut self, id: Json) -> JsonRpcClientRequest {
+        self.id = Some(id);
+        self
+    }
+
+    /**
+     * Set request id taken from generator.
+     * */
+    pub fn with_generated_id(self, generator: &IdGenerator) -> JsonRpcClientRequest {
+        self.with_id(generator.next())
+    }
+
+    /**
+     * Get request id. None for notification.
+     * */
+    pub fn id(&self) -> Option<&Json> {
+        self.id.as_ref()
+    }
+}
+
+impl ToJson for JsonRpcClientRequest {
+    /**
+     * Convert JsonRpcClientRequest to Json
+     * */
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        d.insert("jsonrpc".to_owned(), "2.0".to_json());
+        d.insert("method".to_owned(), self.method.to_json());
+        if let Some(ref params) = self.params {
+            d.insert("params".to_owned(), params.clone());
+        }
+        if let Some(ref id) = self.id {
+            d.insert("id".to_owned(), id.clone());
+        }
+        Json::Object(d)
+    }
+}
+
+/**
+ * Error returned when response could not be parsed.
+ * */
+#[derive(Debug)]
+pub enum ResponseParseError {
+    /**
+     * Response is not valid JSON.
+     * */
+    Parse(ParserError),
+    /**
+     * Got batch response when single response was expected.
+     * */
+    UnexpectedBatch,
+    /**
+     * Got single response when batch response was expected.
+     * */
+    ExpectedBatch,
+    /**
+     * Response is valid JSON, but not valid JSON-RPC 2.0 response.
+     * */
+    InvalidResponse(&'static str),
+    /**
+     * Response id does not match any pending call.
+     * */
+    UnmatchedId(Json),
+}
+
+impl fmt::Display for ResponseParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResponseParseError::Parse(ref e) => write!(f, "invalid JSON: {}", e),
+            ResponseParseError::UnexpectedBatch => write!(f, "unexpected batch response"),
+            ResponseParseError::ExpectedBatch => write!(f, "expected batch response"),
+            ResponseParseError::InvalidResponse(e) => write!(f, "invalid response: {}", e),
+            ResponseParseError::UnmatchedId(ref id) => write!(f, "no pending call with id {}", id),
+        }
+    }
+}
+
+impl ::std::error::Error for ResponseParseError {}
+
+impl From<ParserError> for ResponseParseError {
+    fn from(e: ParserError) -> ResponseParseError {
+        ResponseParseError::Parse(e)
+    }
+}
+
+/**
+ * Error object received from server.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedError {
+    code: i32,
+    message: String,
+    data: Option<Json>,
+}
+
+impl ParsedError {
+    /**
+     * Get code for error
+     * */
+    pub fn get_code(&self) -> i32 {
+        self.code
+    }
+
+    /**
+     * Get error code as `ErrorCode`.
+     * */
+    pub fn get_error_code(&self) -> ErrorCode {
+        ErrorCode::from_code(self.code)
+    }
+
+    /**
+     * Get short description message for error
+     * */
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /**
+     * Get additional data for error.
+     * */
+    pub fn get_data(&self) -> Option<&Json> {
+        self.data.as_ref()
+    }
+
+    /**
+     * Decode additional data into user type. None if there is no data.
+     * */
+    pub fn data_as<T: Decodable>(&self) -> Option<Result<T, DecoderError>> {
+        self.data.as_ref().map(|data| T::decode(&mut Decoder::new(data.clone())))
+    }
+}
+
+/**
+ * Response received from server.
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedResponse {
+    result: Option<Json>,
+    error: Option<ParsedError>,
+    id: Json,
+}
+
+impl ParsedResponse {
+    /**
+     * Get result of successful call. None if server returned error.
+     * */
+    pub fn result(&self) -> Option<&Json> {
+        self.result.as_ref()
+    }
+
+    /**
+     * Get error returned by server. None on success.
+     * */
+    pub fn error(&self) -> Option<&ParsedError> {
+        self.error.as_ref()
+    }
+
+    /**
+     * Get response id. Null if server could not determine request id.
+     * */
+    pub fn id(&self) -> &Json {
+        &self.id
+    }
+
+    /**
+     * Build response from already parsed response object.
+     * */
+    fn from_object(obj: &Object) -> Result<ParsedResponse, ResponseParseError> {
+        if obj.get("jsonrpc").and_then(|o| o.as_string()) != Some("2.0") {
+            return Err(ResponseParseError::InvalidResponse("missing or invalid 'jsonrpc' member"));
+        }
+
+        let id = match obj.get("id") {
+            Some(&Json::Object(_)) | Some(&Json::Array(_)) => {
+                return Err(ResponseParseError::InvalidResponse("invalid 'id' member"))
+            }
+            Some(id) => id.clone(),
+            None => return Err(ResponseParseError::InvalidResponse("missing 'id' member")),
+        };
+
+        match (obj.get("result"), obj.get("error")) {
+            (Some(result), None) => {
+                Ok(ParsedResponse {
+                    result: Some(result.clone()),
+                    error: None,
+                    id,
+                })
+            }
+            (None, Some(error)) => {
+                Ok(ParsedResponse {
+                    result: None,
+                    error: Some(parse_error(error)?),
+                    id,
+                })
+            }
+            _ => Err(ResponseParseError::InvalidResponse("expected exactly one of 'result' and 'error'")),
+        }
+    }
+}
+
+/**
+ * Parse error object from response.
+ * */
+fn parse_error(error: &Json) -> Result<ParsedError, ResponseParseError> {
+    let error = error.as_object()
+                     .ok_or(ResponseParseError::InvalidResponse("'error' is not an object"))?;
+    let code = error.get("code")
+                    .and_then(|c| c.as_i64())
+                    .filter(|&c| c >= i64::from(i32::MIN) && c <= i64::from(i32::MAX))
+                    .ok_or(ResponseParseError::InvalidResponse("missing or invalid error code"))?;
+    let message = error.get("message")
+                       .and_then(|m| m.as_string())
+                       .ok_or(ResponseParseError::InvalidResponse("missing or invalid error message"))?;
+    Ok(ParsedError {
+        code: code as i32,
+        message: message.to_owned(),
+        data: error.get("data").cloned(),
+    })
+}
+
+impl<'a> TryFrom<&'a str> for ParsedResponse {
+    type Error = ResponseParseError;
+
+    /**
+     * Parse single (non batch) response.
+     * */
+    fn try_from(response: &'a str) -> Result<ParsedResponse, ResponseParseError> {
+        match Json::from_str(response)? {
+            Json::Object(ref obj) => ParsedResponse::from_object(obj),
+            Json::Array(_) => Err(ResponseParseError::UnexpectedBatch),
+            _ => Err(ResponseParseError::InvalidResponse("response is not an object")),
+        }
+    }
+}
+
+/**
+ * Parse batch response.
+ * */
+pub fn parse_batch_response(response: &str) -> Result<Vec<ParsedResponse>, ResponseParseError> {
+    match Json::from_str(response)? {
+        Json::Array(ref array) => {
+            array.iter()
+                 .map(|r| {
+                     r.as_object()
+                      .ok_or(ResponseParseError::InvalidResponse("response is not an object"))
+                      .and_then(ParsedResponse::from_object)
+                 })
+                 .collect()
+        }
+        Json::Object(_) => Err(ResponseParseError::ExpectedBatch),
+        _ => Err(ResponseParseError::InvalidResponse("response is not an array")),
+    }
+}
+
+/**
+ * Turn response of upstream server into outcome of proxying method, so downstream client gets
+ * upstream result or error under its own id. `request_id` is id of request sent upstream.
+ * Malformed response or response to other request is reported as `InternalError`.
+ * */
+pub fn forward_response(upstream: &str, request_id: &Json) -> Result<Json, ErrorJsonRpc> {
+    let response = ParsedResponse::try_from(upstream).map_err(|e| {
+        error!("Invalid upstream response: {}", e);
+        ErrorJsonRpc::new(ErrorCode::InternalError)
+    })?;
+    // Numbers parsed from different sources may differ in representation only
+    if response.id.to_string() != request_id.to_string() {
+        error!("Upstream response id {} does not match request id {}", response.id, request_id);
+        return Err(ErrorJsonRpc::new(ErrorCode::InternalError));
+    }
+    match response.error {
+        Some(error) => {
+            let code = ErrorCode::AppError(error.code, error.message);
+            Err(match error.data {
+                Some(data) => ErrorJsonRpc::new_data(code, data),
+                None => ErrorJsonRpc::new(code),
+            })
+        }
+        None => Ok(response.result.unwrap_or(Json::Null)),
+    }
+}
+
+/**
+ * Handle receiving response to single registered call.
+ * */
+#[derive(Debug)]
+pub struct PendingCall {
+    receiver: Receiver<ParsedResponse>,
+}
+
+impl PendingCall {
+    /**
+     * Block until response arrives. None if call was dropped from `PendingCalls` unresolved.
+     * */
+    pub fn wait(self) -> Option<ParsedResponse> {
+        self.receiver.recv().ok()
+    }
+
+    /**
+     * Get response if it already arrived.
+     * */
+    pub fn try_get(&self) -> Option<ParsedResponse> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/**
+ * Outbound calls waiting for responses, matched by request id. Safe to share between threads.
+ * */
+#[derive(Debug, Default)]
+pub struct PendingCalls {
+    calls: Mutex<HashMap<String, Sender<ParsedResponse>>>,
+}
+
+impl PendingCalls {
+    /**
+     * Create empty registry.
+     * */
+    pub fn new() -> PendingCalls {
+        Default::default()
+    }
+
+    /**
+     * Record call with given id. Registering the same id again replaces previous call.
+     * */
+    pub fn register(&self, id: &Json) -> PendingCall {
+        let (sender, receiver) = channel();
+        self.calls.lock().unwrap().insert(id.to_string(), sender);
+        PendingCall { receiver }
+    }
+
+    /**
+     * Deliver single or batch response to registered calls. Responses are delivered even if
+     * some of them are invalid or unmatched, first such failure is returned.
+     * */
+    pub fn resolve(&self, response: &Json) -> Result<(), ResponseParseError> {
+        match *response {
+            Json::Array(ref array) => {
+                array.iter().map(|r| self.resolve_single(r)).fold(Ok(()), Result::and)
+            }
+            _ => self.resolve_single(response),
+        }
+    }
+
+    fn resolve_single(&self, response: &Json) -> Result<(), ResponseParseError> {
+        let response = response.as_object()
+                               .ok_or(ResponseParseError::InvalidResponse("response is not an object"))
+                               .and_then(ParsedResponse::from_object)?;
+        let sender = self.calls.lock().unwrap().remove(&response.id.to_string());
+        match sender {
+            // Caller could have stopped waiting, which is not an error
+            Some(sender) => {
+                let _ = sender.send(response);
+                Ok(())
+            }
+            None => Err(ResponseParseError::UnmatchedId(response.id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_generated_ids() {
+        let generator = IdGenerator::new();
+        let first = JsonRpcClientRequest::new("sum").with_generated_id(&generator);
+        let second = JsonRpcClientRequest::new("sum").with_generated_id(&generator);
+        let first_id = first.id().and_then(|id| id.as_u64()).unwrap();
+        let second_id = second.id().and_then(|id| id.as_u64()).unwrap();
+        assert!(second_id > first_id);
+
+        let expected_request = Json::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \
+                                               \"id\": 1}").unwrap();
+        assert_eq!(expected_request, first.to_json());
+    }
+
+    #[test]
+    fn test_parse_result() {
+        let response = ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}")
+                           .unwrap();
+        assert_eq!(Some(19), response.result().and_then(|r| r.as_i64()));
+        assert_eq!(None, response.error());
+        assert_eq!(Some(1), response.id().as_i64());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let response = ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                 -32601, \"message\": \"Method not found\"}, \
+                                                 \"id\": \"1\"}")
+                           .unwrap();
+        assert_eq!(None, response.result());
+        let error = response.error().unwrap();
+        assert_eq!(-32601, error.get_code());
+        match error.get_error_code() {
+            ErrorCode::MethodNotFound => {}
+            other => panic!("Unexpected error code {:?}", other),
+        }
+        assert_eq!("Method not found", error.get_message());
+        assert_eq!(None, error.get_data());
+        assert_eq!(&"1".to_json(), response.id());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct RetryInfo {
+        retry_after: u32,
+    }
+
+    impl Decodable for RetryInfo {
+        fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<RetryInfo, D::Error> {
+            d.read_struct("RetryInfo", 1, |d| {
+                Ok(RetryInfo { retry_after: d.read_struct_field("retryAfter", 0, Decodable::decode)? })
+            })
+        }
+    }
+
+    #[test]
+    fn test_error_data_as() {
+        let response = ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                 -32000, \"message\": \"Busy\", \"data\": \
+                                                 {\"retryAfter\": 30}}, \"id\": 1}")
+                           .unwrap();
+        let error = response.error().unwrap();
+        assert_eq!(RetryInfo { retry_after: 30 }, error.data_as::<RetryInfo>().unwrap().unwrap());
+        assert!(error.data_as::<String>().unwrap().is_err());
+
+        let response = ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                 -32000, \"message\": \"Busy\"}, \"id\": 1}")
+                           .unwrap();
+        assert!(response.error().unwrap().data_as::<RetryInfo>().is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        match ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"result\": 19") {
+            Err(ResponseParseError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match ParsedResponse::try_from("[{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}]") {
+            Err(ResponseParseError::UnexpectedBatch) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match ParsedResponse::try_from("{\"jsonrpc\": \"2.0\", \"result\": 19, \"error\": \
+                                        {\"code\": -32603, \"message\": \"Internal error\"}, \
+                                        \"id\": 1}") {
+            Err(ResponseParseError::InvalidResponse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let responses = parse_batch_response("[{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": \"1\"},
+            {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \"message\": \"Invalid \
+                                              Request\"}, \"id\": null}]")
+                            .unwrap();
+        assert_eq!(2, responses.len());
+        assert_eq!(Some(7), responses[0].result().and_then(|r| r.as_i64()));
+        assert_eq!(-32600, responses[1].error().unwrap().get_code());
+        assert_eq!(&Json::Null, responses[1].id());
+
+        match parse_batch_response("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}") {
+            Err(ResponseParseError::ExpectedBatch) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pending_calls() {
+        let pending = PendingCalls::new();
+        let first = pending.register(&1.to_json());
+        let second = pending.register(&"2".to_json());
+        assert_eq!(None, first.try_get());
+
+        let response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": \"2\"}, \
+                                       {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                       \"message\": \"Method not found\"}, \"id\": 1}]")
+                           .unwrap();
+        pending.resolve(&response).unwrap();
+        assert_eq!(-32601, first.wait().unwrap().error().unwrap().get_code());
+        assert_eq!(Some(7), second.try_get().unwrap().result().and_then(|r| r.as_i64()));
+    }
+
+    #[test]
+    fn test_orphan_response() {
+        let pending = PendingCalls::new();
+        let call = pending.register(&1.to_json());
+        let response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": 5}").unwrap();
+        match pending.resolve(&response) {
+            Err(ResponseParseError::UnmatchedId(ref id)) if id.as_i64() == Some(5) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(None, call.try_get());
+
+        // Already resolved call does not match again
+        let response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": 1}").unwrap();
+        pending.resolve(&response).unwrap();
+        match pending.resolve(&response) {
+            Err(ResponseParseError::UnmatchedId(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        drop(pending);
+        assert!(call.wait().is_some());
+    }
+
+    #[test]
+    fn test_forward_response() {
+        let upstream = "{\"jsonrpc\": \"2.0\", \"result\": {\"sum\": 3}, \"id\": 7}";
+        assert_eq!(Json::from_str("{\"sum\": 3}").unwrap(), forward_response(upstream, &7.to_json()).unwrap());
+
+        let upstream = "{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32001, \"message\": \"Busy\", \
+                        \"data\": 5}, \"id\": \"up\"}";
+        let error = forward_response(upstream, &"up".to_json()).unwrap_err();
+        assert_eq!(-32001, error.get_code());
+        assert_eq!("Busy", error.get_message());
+        assert_eq!(Some(5), error.get_data().and_then(Json::as_i64));
+
+        // Response to other request and malformed response
+        let error = forward_response(upstream, &1.to_json()).unwrap_err();
+        assert_eq!(-32603, error.get_code());
+        let error = forward_response("{\"result\": 1}", &1.to_json()).unwrap_err();
+        assert_eq!(-32603, error.get_code());
+    }
+}
@@ -0,0 +1,124 @@
+//! Parameter coercion rules shared between methods.
+use rustc_serialize::json::Json;
+use std::collections::HashMap;
+
+use {typed_param, ErrorCode, ErrorJsonRpc, JsonRpcRequest};
+
+/**
+ * Rule converting parameter value to expected form. None if value can't be converted.
+ * */
+pub type Coercion = Box<dyn Fn(&Json) -> Option<Json> + 'static + Sync + Send>;
+
+/**
+ * Coercion rules registered under type tags (eg. `"integer"`), so methods refer to tag
+ * instead of repeating conversion logic.
+ * */
+#[derive(Default)]
+pub struct CoercionRegistry {
+    rules: HashMap<String, Coercion>,
+}
+
+impl CoercionRegistry {
+    /**
+     * Create registry without rules.
+     * */
+    pub fn new() -> CoercionRegistry {
+        Default::default()
+    }
+
+    /**
+     * Register rule for type tag, replacing previous one.
+     * */
+    pub fn register<F>(&mut self, tag: &str, rule: F)
+        where F: Fn(&Json) -> Option<Json> + 'static + Sync + Send
+    {
+        self.rules.insert(tag.to_owned(), Box::new(rule));
+    }
+
+    /**
+     * Coerce value using rule registered for tag. None if there is no such rule
+     * or value can't be converted.
+     * */
+    pub fn coerce(&self, tag: &str, value: &Json) -> Option<Json> {
+        self.rules.get(tag).and_then(|rule| rule(value))
+    }
+
+    /**
+     * Coerce parameter, reporting Invalid params if it is missing or can't be converted.
+     * Unknown tag is a bug in method and is reported as Internal error.
+     * */
+    fn coerce_param(&self, param: Option<&Json>, name: &str, tag: &str) -> Result<Json, ErrorJsonRpc> {
+        if !self.rules.contains_key(tag) {
+            error!("No coercion rule for '{}'", tag);
+            return Err(ErrorJsonRpc::new(ErrorCode::InternalError));
+        }
+        typed_param(param, name, tag, |value| self.coerce(tag, value))
+    }
+}
+
+impl<'a> JsonRpcRequest<'a> {
+    /**
+     * Get positional parameter coerced with rule registered for tag.
+     * */
+    pub fn param_at_coerced(&self, index: usize, tag: &str, registry: &CoercionRegistry)
+                            -> Result<Json, ErrorJsonRpc> {
+        registry.coerce_param(self.param_at(index), &index.to_string(), tag)
+    }
+
+    /**
+     * Get named parameter coerced with rule registered for tag.
+     * */
+    pub fn param_named_coerced(&self, name: &str, tag: &str, registry: &CoercionRegistry)
+                               -> Result<Json, ErrorJsonRpc> {
+        registry.coerce_param(self.param_named(name), &format!("'{}'", name), tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::ToJson;
+    use std::sync::Arc;
+    use {HashMapWithMethods, JsonRpcServer};
+
+    fn registry() -> CoercionRegistry {
+        let mut registry = CoercionRegistry::new();
+        registry.register("integer", |value| match *value {
+            Json::String(ref s) => s.trim().parse::<i64>().ok().map(Json::I64),
+            _ => value.as_i64().map(Json::I64),
+        });
+        registry
+    }
+
+    #[test]
+    fn test_coerced_params() {
+        let registry = Arc::new(registry());
+        let mut handler = HashMapWithMethods::new();
+        let rules = registry.clone();
+        handler.insert("double".to_owned(), Box::new(move |req| {
+            let value = req.param_at_coerced(0, "integer", &rules)?;
+            Ok((value.as_i64().unwrap_or_default() * 2).to_json())
+        }));
+        handler.insert("unknown".to_owned(), Box::new(move |req| req.param_named_coerced("a", "date", &registry)));
+        let server = JsonRpcServer::new_handler(handler);
+
+        for &(params, result) in &[("[21]", "42"), ("[\"21\"]", "42")] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"double\", \"params\": {}, \"id\": 1}}",
+                                  params);
+            let expected_response = Json::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"result\": {}, \"id\": 1}}",
+                                                            result));
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(&request).unwrap()));
+        }
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"double\", \"params\": [\"x\"], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32602, \
+                                                \"message\": \"Invalid params\", \"data\": \
+                                                \"parameter 0 must be integer\"}, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"unknown\", \"params\": {\"a\": 1}, \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32603, \
+                                                \"message\": \"Internal error\"}, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+}
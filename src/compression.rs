@@ -0,0 +1,170 @@
+//! Support for compressed request bodies (enabled with `compression` feature).
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use {Handler, JsonRpcServer};
+
+/**
+ * Compression applied to request body, as announced by `Content-Encoding` header.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /**
+     * Body is not compressed.
+     * */
+    Identity,
+    /**
+     * Body is compressed with gzip.
+     * */
+    Gzip,
+    /**
+     * Body is compressed with deflate (zlib wrapped, as used by HTTP).
+     * */
+    Deflate,
+}
+
+impl FromStr for Encoding {
+    type Err = CompressionError;
+
+    /**
+     * Parse value of `Content-Encoding` header.
+     * */
+    fn from_str(s: &str) -> Result<Encoding, CompressionError> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(Encoding::Identity),
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            other => Err(CompressionError::UnsupportedEncoding(other.to_owned())),
+        }
+    }
+}
+
+/**
+ * Error returned when request body could not be decompressed.
+ * It is reported to caller instead of JSON-RPC response, so transport can answer accordingly
+ * (eg. with 415 Unsupported Media Type or 400 Bad Request).
+ * */
+#[derive(Debug)]
+pub enum CompressionError {
+    /**
+     * Requested encoding is not supported.
+     * */
+    UnsupportedEncoding(String),
+    /**
+     * Body is not valid stream for given encoding.
+     * */
+    CorruptData(io::Error),
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CompressionError::UnsupportedEncoding(ref e) => write!(f, "unsupported encoding '{}'", e),
+            CompressionError::CorruptData(ref e) => write!(f, "corrupt compressed data: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for CompressionError {}
+
+/**
+ * Read decompressed data, stopping one byte past limit, so oversized body is detected
+ * without inflating it whole.
+ * */
+fn inflate<R: Read>(mut decoder: R, limit: Option<usize>, out: &mut Vec<u8>) -> io::Result<usize> {
+    match limit {
+        Some(limit) => decoder.take(limit as u64 + 1).read_to_end(out),
+        None => decoder.read_to_end(out),
+    }
+}
+
+/**
+ * Decompress body using given encoding. With limit, at most `limit + 1` bytes are produced.
+ * */
+fn decompress(bytes: &[u8], encoding: Encoding, limit: Option<usize>) -> Result<Vec<u8>, CompressionError> {
+    let mut out = Vec::new();
+    let result = match encoding {
+        Encoding::Identity => {
+            out.extend_from_slice(bytes);
+            Ok(out.len())
+        }
+        Encoding::Gzip => inflate(GzDecoder::new(bytes), limit, &mut out),
+        Encoding::Deflate => inflate(ZlibDecoder::new(bytes), limit, &mut out),
+    };
+    result.map(|_| out).map_err(CompressionError::CorruptData)
+}
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Decompress request and process it like `handle_request_bytes`.
+     * Decompression failures are returned as `Err`, while body which is not valid text
+     * or JSON is answered with regular Parse error response. Decompressed body is subject
+     * to `set_max_request_bytes` limit, decompression stops as soon as it is exceeded.
+     * */
+    pub fn handle_request_compressed(&self,
+                                     bytes: &[u8],
+                                     encoding: Encoding,
+                                     custom: &H::Context)
+                                     -> Result<Option<String>, CompressionError> {
+        let body = decompress(bytes, encoding, self.options.max_request_bytes)?;
+        Ok(self.handle_request_bytes(&body, custom))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rustc_serialize::json::Json;
+    use std::io::Write;
+    use tests::subtract_server as server;
+
+    #[test]
+    fn test_gzip_request() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \
+                       \"id\": 1}";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(request.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = server().handle_request_compressed(&compressed, Encoding::Gzip, &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+    }
+
+    #[test]
+    fn test_corrupt_data() {
+        let result = server().handle_request_compressed(b"{\"not\": \"gzip\"}", Encoding::Gzip, &());
+        match result {
+            Err(CompressionError::CorruptData(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_encoding() {
+        assert_eq!(Ok(Encoding::Gzip), "GZIP".parse().map_err(|_| ()));
+        match "br".parse::<Encoding>() {
+            Err(CompressionError::UnsupportedEncoding(ref e)) if e == "br" => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compression_bomb() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b' '; 10 * 1024 * 1024]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(Some(1025), decompress(&compressed, Encoding::Gzip, Some(1024)).ok().map(|b| b.len()));
+
+        let mut server = server();
+        server.set_max_request_bytes(Some(1024));
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \
+                                                \"message\": \"Invalid Request\"}, \"id\": null}");
+        let response = server.handle_request_compressed(&compressed, Encoding::Gzip, &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+    }
+}
@@ -0,0 +1,438 @@
+//! Standard request context carrying call metadata.
+use rustc_serialize::json::Json;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use {Deprecation, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest, JsonRpcServer, JsonRpcServerBuilder,
+     StreamingHandlerResult};
+
+/**
+ * Context wrapper carrying request deadline together with user defined context.
+ * */
+#[derive(Debug, Default)]
+pub struct RpcContext<C> {
+    /**
+     * Moment after which result of request is no longer needed.
+     * */
+    pub deadline: Option<Instant>,
+
+    /**
+     * User defined context.
+     * */
+    pub context: C,
+}
+
+impl<C> RpcContext<C> {
+    /**
+     * Wrap context without deadline.
+     * */
+    pub fn new(context: C) -> RpcContext<C> {
+        RpcContext {
+            deadline: None,
+            context,
+        }
+    }
+
+    /**
+     * Wrap context with deadline.
+     * */
+    pub fn with_deadline(context: C, deadline: Instant) -> RpcContext<C> {
+        RpcContext {
+            deadline: Some(deadline),
+            context,
+        }
+    }
+
+    /**
+     * Check if deadline already passed.
+     * */
+    pub fn is_expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/**
+ * Handler refusing to start work for requests with exceeded deadline.
+ * Wrapped handler receives the same `RpcContext`, so deadline can be propagated further.
+ * */
+pub struct DeadlineHandler<H> {
+    inner: H,
+}
+
+impl<H> DeadlineHandler<H> {
+    /**
+     * Wrap handler.
+     * */
+    pub fn new(inner: H) -> DeadlineHandler<H> {
+        DeadlineHandler { inner }
+    }
+
+    /**
+     * Get wrapped handler reference
+     * */
+    pub fn get_inner(&self) -> &H {
+        &self.inner
+    }
+
+    /**
+     * Get mutable wrapped handler reference
+     * */
+    pub fn get_inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+}
+
+/**
+ * Reject request whose deadline already passed.
+ * */
+fn check_deadline<C>(req: &JsonRpcRequest, custom: &RpcContext<C>) -> Result<(), ErrorJsonRpc> {
+    if custom.is_expired() {
+        warn!("Deadline exceeded before calling '{}'", req.method);
+        return Err(ErrorJsonRpc::new(ErrorCode::ServerError(-32000, "deadline exceeded")));
+    }
+    Ok(())
+}
+
+impl<C, H: Handler<Context = RpcContext<C>>> Handler for DeadlineHandler<H> {
+    type Context = RpcContext<C>;
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        check_deadline(req, custom)?;
+        self.inner.handle_outcome(req, custom)
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        check_deadline(req, custom)?;
+        self.inner.handle_streaming(req, custom, sink)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.inner.deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.inner.has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.inner.is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.inner.requires_response(method)
+    }
+}
+
+/**
+ * Context which can tell that results are no longer needed (eg. client disconnected).
+ * */
+pub trait Cancellable {
+    /**
+     * Check if processing should stop.
+     * */
+    fn is_cancelled(&self) -> bool;
+}
+
+/**
+ * Flag shared between transport and request processing. Clones refer to the same flag.
+ * */
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /**
+     * Create token which is not cancelled.
+     * */
+    pub fn new() -> CancellationToken {
+        Default::default()
+    }
+
+    /**
+     * Cancel processing. Calls which already started are not interrupted.
+     * */
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Cancellable for CancellationToken {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl<C: Cancellable> Cancellable for RpcContext<C> {
+    fn is_cancelled(&self) -> bool {
+        self.context.is_cancelled()
+    }
+}
+
+/**
+ * Handler refusing to start work once context is cancelled, so remaining requests
+ * of batch are answered with `ServerError(-32000, "cancelled")` without calling wrapped handler.
+ * */
+pub struct CancellableHandler<H> {
+    inner: H,
+}
+
+impl<H> CancellableHandler<H> {
+    /**
+     * Wrap handler.
+     * */
+    pub fn new(inner: H) -> CancellableHandler<H> {
+        CancellableHandler { inner }
+    }
+
+    /**
+     * Get wrapped handler reference
+     * */
+    pub fn get_inner(&self) -> &H {
+        &self.inner
+    }
+
+    /**
+     * Get mutable wrapped handler reference
+     * */
+    pub fn get_inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+}
+
+/**
+ * Reject request whose processing was cancelled.
+ * */
+fn check_cancelled<C: Cancellable>(req: &JsonRpcRequest, custom: &C) -> Result<(), ErrorJsonRpc> {
+    if custom.is_cancelled() {
+        warn!("Request cancelled before calling '{}'", req.method);
+        return Err(ErrorJsonRpc::new(ErrorCode::ServerError(-32000, "cancelled")));
+    }
+    Ok(())
+}
+
+impl<H: Handler> Handler for CancellableHandler<H> where H::Context: Cancellable {
+    type Context = H::Context;
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        check_cancelled(req, custom)?;
+        self.inner.handle_outcome(req, custom)
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        check_cancelled(req, custom)?;
+        self.inner.handle_streaming(req, custom, sink)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.inner.deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.inner.has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.inner.is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.inner.requires_response(method)
+    }
+}
+
+/**
+ * Context receiving correlation id of request before handler is called (see
+ * `JsonRpcServer::set_propagate_id`). Context is shared, so it needs interior mutability.
+ * */
+pub trait SetCorrelationId {
+    /**
+     * Remember correlation id of request about to be processed.
+     * */
+    fn set_correlation_id(&self, id: &str);
+}
+
+/**
+ * Generate uuid-like id for notification.
+ * */
+fn generated_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(nanos);
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    let high = hasher.finish();
+    hasher.write_u64(high);
+    let low = hasher.finish();
+    format!("{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xfff,
+            0x8000 | ((low >> 48) & 0x3fff),
+            low & 0xffff_ffff_ffff)
+}
+
+/**
+ * Pass id of request (string ids as is, others serialized) to context.
+ * */
+fn propagate_id<C: SetCorrelationId>(context: &C, id: Option<&Json>) {
+    let id = match id {
+        Some(Json::String(id)) => id.clone(),
+        Some(id) => id.to_string(),
+        None => generated_id(),
+    };
+    context.set_correlation_id(&id);
+}
+
+impl<H: Handler> JsonRpcServer<H> where H::Context: SetCorrelationId {
+    /**
+     * Copy id of each valid request into context before it is authorized and handled, so it
+     * can be included in logs. Notifications get generated uuid-like id. Disabled by default.
+     * */
+    pub fn set_propagate_id(&mut self, propagate: bool) {
+        self.id_propagator = if propagate { Some(propagate_id::<H::Context>) } else { None };
+    }
+}
+
+impl<H: Handler> JsonRpcServerBuilder<H> where H::Context: SetCorrelationId {
+    /**
+     * See `JsonRpcServer::set_propagate_id`.
+     * */
+    pub fn propagate_id(mut self, propagate: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_propagate_id(propagate);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use {JsonRpcServer, JsonRpcServerBuilder};
+
+    struct Sum;
+    impl Handler for Sum {
+        type Context = RpcContext<()>;
+        fn handle(&self, _: &JsonRpcRequest, _: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+            Ok(7.to_json())
+        }
+    }
+
+    #[test]
+    fn test_deadline_exceeded() {
+        let server = JsonRpcServer::new_handler(DeadlineHandler::new(Sum));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}";
+        let context = RpcContext::with_deadline((), Instant::now());
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32000, \"message\": \"deadline exceeded\"}, \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request_context(request, &context).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_deadline_not_exceeded() {
+        let server = JsonRpcServer::new_handler(DeadlineHandler::new(Sum));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}";
+        let context = RpcContext::with_deadline((), Instant::now() + Duration::from_secs(60));
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request_context(request, &context).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Without deadline
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    struct Stopper;
+    impl Handler for Stopper {
+        type Context = CancellationToken;
+        fn handle(&self, req: &JsonRpcRequest, token: &CancellationToken) -> Result<Json, ErrorJsonRpc> {
+            if req.method == "stop" {
+                token.cancel();
+            }
+            Ok(true.to_json())
+        }
+    }
+
+    #[test]
+    fn test_cancelled_batch() {
+        let server = JsonRpcServer::new_handler(CancellableHandler::new(Stopper));
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"work\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"stop\", \"id\": 2}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"work\", \"id\": 3}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"work\", \"id\": 4}]";
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": true, \"id\": 1}, \
+                                                {\"jsonrpc\": \"2.0\", \"result\": true, \"id\": 2}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \
+                                                \"message\": \"cancelled\"}, \"id\": 3}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \
+                                                \"message\": \"cancelled\"}, \"id\": 4}]");
+        let token = CancellationToken::new();
+        let response = Json::from_str(&server.handle_request_context(request, &token).unwrap());
+        assert_eq!(expected_response, response);
+        assert!(token.is_cancelled());
+    }
+
+    #[derive(Default)]
+    struct Traced {
+        correlation_id: Mutex<Option<String>>,
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl SetCorrelationId for Traced {
+        fn set_correlation_id(&self, id: &str) {
+            *self.correlation_id.lock().unwrap() = Some(id.to_owned());
+        }
+    }
+
+    struct Logged;
+    impl Handler for Logged {
+        type Context = Traced;
+        fn handle(&self, _: &JsonRpcRequest, context: &Traced) -> Result<Json, ErrorJsonRpc> {
+            let id = context.correlation_id.lock().unwrap().clone().unwrap_or_default();
+            context.seen.lock().unwrap().push(id);
+            Ok(Json::Null)
+        }
+    }
+
+    #[test]
+    fn test_propagate_id() {
+        let server = JsonRpcServerBuilder::new(Logged).propagate_id(true).build();
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"log\", \"id\": 7}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"log\", \"id\": \"abc\"}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"log\"}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"log\"}]";
+        let context = Traced::default();
+        server.handle_request_context(request, &context);
+        let seen = context.seen.lock().unwrap();
+        assert_eq!(&["7", "abc"], &seen[..2]);
+        assert_eq!(36, seen[2].len());
+        assert_ne!(seen[2], seen[3]);
+
+        let mut server = server;
+        server.set_propagate_id(false);
+        let context = Traced::default();
+        server.handle_request_context("{\"jsonrpc\": \"2.0\", \"method\": \"log\", \"id\": 1}", &context);
+        assert_eq!(vec![String::new()], *context.seen.lock().unwrap());
+    }
+}
@@ -0,0 +1,172 @@
+//! Reading requests framed with `Content-Length` header (as used by LSP).
+use std::io::{self, BufRead, Read};
+
+use {Handler, JsonRpcServer};
+
+/**
+ * Limit of headers section of single message, including line terminators.
+ * */
+const MAX_HEADER_BYTES: usize = 8192;
+
+/**
+ * Reader of `Content-Length` framed messages:
+ * headers terminated by empty line, followed by exactly `Content-Length` bytes of body.
+ * */
+pub struct FrameReader<R> {
+    reader: R,
+    max_length: Option<usize>,
+}
+
+/**
+ * Build error for malformed header.
+ * */
+fn invalid_header(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: BufRead> FrameReader<R> {
+    /**
+     * Wrap reader.
+     * */
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader {
+            reader,
+            max_length: None,
+        }
+    }
+
+    /**
+     * Reject messages with `Content-Length` over limit before their body is read.
+     * */
+    pub fn max_length(mut self, limit: Option<usize>) -> FrameReader<R> {
+        self.max_length = limit;
+        self
+    }
+
+    /**
+     * Read body of next message. Returns None if stream ended before next message.
+     * Malformed headers, headers longer than 8 KiB and `Content-Length` over limit are reported
+     * as `InvalidData` errors.
+     * */
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut length = None;
+        let mut budget = MAX_HEADER_BYTES;
+        loop {
+            if budget == 0 {
+                return Err(invalid_header("headers too long".to_owned()));
+            }
+            let mut line = String::new();
+            let read = (&mut self.reader).take(budget as u64).read_line(&mut line)?;
+            if read == 0 {
+                if budget == MAX_HEADER_BYTES {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unterminated headers"));
+            }
+            budget -= read;
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':')
+                                    .ok_or_else(|| invalid_header(format!("malformed header '{}'", line)))?;
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                let value = value.trim();
+                length = Some(value.parse::<usize>()
+                                   .map_err(|_| invalid_header(format!("invalid Content-Length '{}'", value)))?);
+            }
+        }
+        let length = length.ok_or_else(|| invalid_header("missing Content-Length".to_owned()))?;
+        if self.max_length.is_some_and(|limit| length > limit) {
+            return Err(invalid_header(format!("Content-Length {} exceeds limit", length)));
+        }
+        // Buffer grows with data actually received, not with announced length
+        let mut body = Vec::new();
+        (&mut self.reader).take(length as u64).read_to_end(&mut body)?;
+        if body.len() < length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated body"));
+        }
+        Ok(Some(body))
+    }
+
+    /**
+     * Get wrapped reader back.
+     * */
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Read single framed request and process it like `handle_request_bytes`.
+     * End of stream before request is reported as `UnexpectedEof` error, request longer than
+     * `set_max_request_bytes` limit as `InvalidData` error (its body is not read).
+     * */
+    pub fn handle_framed<R: BufRead>(&self, reader: &mut R, custom: &H::Context) -> io::Result<Option<String>> {
+        match FrameReader::new(reader).max_length(self.options.max_request_bytes).read_frame()? {
+            Some(body) => Ok(self.handle_request_bytes(&body, custom)),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no request")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::Json;
+    use std::io::Cursor;
+    use tests::subtract_server as server;
+
+    #[test]
+    fn test_framed_request() {
+        let body = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let message = format!("Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}\
+                               Content-Length: 2\r\n\r\n[]",
+                              body.len(),
+                              body);
+        let mut reader = Cursor::new(message.into_bytes());
+        let server = server();
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = server.handle_framed(&mut reader, &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = server.handle_framed(&mut reader, &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+
+        let error = server.handle_framed(&mut reader, &()).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        for message in &["Content-Length 10\r\n\r\n{}", "Content-Length: ten\r\n\r\n{}", "\r\n{}"] {
+            let error = server().handle_framed(&mut Cursor::new(message.as_bytes()), &()).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        }
+
+        let error = server().handle_framed(&mut Cursor::new(&b"Content-Length: 10\r\n\r\n{}"[..]), &())
+                            .unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    }
+
+    #[test]
+    fn test_frame_limits() {
+        let mut server = server();
+        server.set_max_request_bytes(Some(1024));
+        let message = "Content-Length: 18446744073709551615\r\n\r\n{}";
+        let error = server.handle_framed(&mut Cursor::new(message.as_bytes()), &()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+        let message = format!("X-Padding: {}\r\nContent-Length: 2\r\n\r\n[]", "x".repeat(MAX_HEADER_BYTES));
+        let error = server.handle_framed(&mut Cursor::new(message.into_bytes()), &()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+
+        let mut reader = FrameReader::new(Cursor::new(&b"Content-Length: 2\r\n\r\n[]"[..])).max_length(Some(2));
+        assert_eq!(Some(b"[]".to_vec()), reader.read_frame().unwrap());
+    }
+}
@@ -0,0 +1,100 @@
+//! Opt-in `initialize` handshake for LSP-like clients.
+use rustc_serialize::json::Json;
+use std::sync::atomic::Ordering;
+
+use {Handler, JsonRpcServer, JsonRpcServerBuilder};
+
+/**
+ * Name of built-in handshake method.
+ * */
+pub const INITIALIZE_METHOD: &str = "initialize";
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Answer `initialize` method with given capabilities (eg. registered methods, version and
+     * custom flags) instead of passing it to handler, and mark server as initialized.
+     * Repeated handshake is answered the same way.
+     * */
+    pub fn enable_initialize(&mut self, capabilities: Json) {
+        self.capabilities = Some(capabilities);
+    }
+
+    /**
+     * Reject calls other than `initialize` until handshake is done. Has effect only when
+     * `enable_initialize` was called. Disabled by default.
+     * */
+    pub fn set_require_initialize(&mut self, require: bool) {
+        self.options.require_initialize = require;
+    }
+
+    /**
+     * Check if `initialize` method was called.
+     * */
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+}
+
+impl<H: Handler> JsonRpcServerBuilder<H> {
+    /**
+     * See `JsonRpcServer::enable_initialize`.
+     * */
+    pub fn initialize(mut self, capabilities: Json) -> JsonRpcServerBuilder<H> {
+        self.server.enable_initialize(capabilities);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_require_initialize`.
+     * */
+    pub fn require_initialize(mut self, require: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_require_initialize(require);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::{Json, ToJson};
+    use {HashMapWithMethods, JsonRpcServerBuilder};
+
+    fn capabilities() -> Json {
+        Json::from_str("{\"version\": \"1.2\", \"methods\": [\"echo\"], \"streaming\": false}").unwrap()
+    }
+
+    fn handler() -> HashMapWithMethods {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("echo".to_owned(), Box::new(|_| Ok("echo".to_json())));
+        handler
+    }
+
+    #[test]
+    fn test_initialize() {
+        let server = JsonRpcServerBuilder::new(handler()).initialize(capabilities()).build();
+        assert!(!server.is_initialized());
+        let response = server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"initialize\", \"id\": 1}");
+        let response = Json::from_str(&response.unwrap()).unwrap();
+        assert_eq!(Some(&capabilities()), response.find("result"));
+        assert!(server.is_initialized());
+
+        // Not enforced, so calls work regardless of handshake
+        let server = JsonRpcServerBuilder::new(handler()).initialize(capabilities()).build();
+        let response = server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"id\": 2}");
+        assert!(response.unwrap().contains("\"result\":\"echo\""));
+    }
+
+    #[test]
+    fn test_require_initialize() {
+        let server = JsonRpcServerBuilder::new(handler())
+                         .initialize(capabilities())
+                         .require_initialize(true)
+                         .build();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32002, \
+                                                \"message\": \"Server not initialized\"}, \"id\": 2}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"initialize\", \"id\": 1}");
+        assert!(server.handle_request(request).unwrap().contains("\"result\":\"echo\""));
+    }
+}
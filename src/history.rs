@@ -0,0 +1,85 @@
+//! In-memory log of recently processed requests, for live debugging.
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/**
+ * Request with its serialized response (None if nothing was sent back).
+ * */
+pub type Exchange = (String, Option<String>);
+
+/**
+ * Ring buffer keeping last `capacity` exchanges. Capacity 0 disables recording.
+ * */
+#[derive(Default)]
+pub struct History {
+    capacity: usize,
+    entries: Mutex<VecDeque<Exchange>>,
+}
+
+impl History {
+    /**
+     * Change capacity, dropping oldest entries not fitting anymore.
+     * */
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let entries = self.entries.get_mut().unwrap_or_else(|e| e.into_inner());
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /**
+     * Remember exchange, evicting oldest one when buffer is full.
+     * */
+    pub fn record(&self, request: &str, response: Option<&String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((request.to_owned(), response.cloned()));
+    }
+
+    /**
+     * Last `n` exchanges, oldest first.
+     * */
+    pub fn recent(&self, n: usize) -> Vec<Exchange> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().skip(entries.len().saturating_sub(n)).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::ToJson;
+    use {HashMapWithMethods, JsonRpcServer};
+
+    #[test]
+    fn test_history() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("one".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let mut server = JsonRpcServer::new_handler(handler);
+        let request = |id: u32| format!("{{\"jsonrpc\": \"2.0\", \"method\": \"one\", \"id\": {}}}", id);
+
+        // Disabled by default
+        server.handle_request(&request(0));
+        assert!(server.recent(10).is_empty());
+
+        server.set_history_capacity(3);
+        for id in 1..6 {
+            server.handle_request(&request(id));
+        }
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"one\"}");
+        let recent = server.recent(10);
+        let requests: Vec<String> = recent.iter().map(|e| e.0.clone()).collect();
+        assert_eq!(vec![request(4), request(5), "{\"jsonrpc\": \"2.0\", \"method\": \"one\"}".to_owned()], requests);
+        assert_eq!(Some("{\"id\":5,\"jsonrpc\":\"2.0\",\"result\":1}".to_owned()), recent[1].1);
+        assert_eq!(None, recent[2].1);
+        assert_eq!(recent[1..].to_vec(), server.recent(2));
+
+        server.set_history_capacity(1);
+        assert_eq!(recent[2..].to_vec(), server.recent(10));
+    }
+}
@@ -0,0 +1,90 @@
+//! Helpers keeping HTTP transports consistent in content negotiation.
+use ErrorCode;
+
+/**
+ * Content type of responses sent over HTTP.
+ * */
+pub const RESPONSE_CONTENT_TYPE: &str = "application/json";
+
+/**
+ * Media types under which JSON-RPC responses can be delivered.
+ * */
+const ACCEPTED_TYPES: [&str; 3] = ["application/json", "application/json-rpc", "application/jsonrequest"];
+
+/**
+ * Check if response can be sent to client with given `Accept` header.
+ * Empty header accepts anything, media ranges with `q=0` are ignored.
+ * */
+pub fn acceptable_content_type(header: &str) -> bool {
+    if header.trim().is_empty() {
+        return true;
+    }
+    header.split(',').any(|range| {
+        let mut parts = range.split(';');
+        let media = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let rejected = parts.filter_map(|p| p.split_once('='))
+                            .filter(|&(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                            .any(|(_, value)| value.trim().parse::<f32>().is_ok_and(|q| q <= 0.0));
+        !rejected && (media == "*/*" || media == "application/*" || ACCEPTED_TYPES.contains(&media.as_str()))
+    })
+}
+
+/**
+ * HTTP status for outcome of `handle_request`: 200 when response should be sent,
+ * 204 No Content when there is nothing to send (notifications).
+ * */
+pub fn response_status(response: Option<&str>) -> u16 {
+    match response {
+        Some(_) => 200,
+        None => 204,
+    }
+}
+
+impl ErrorCode {
+    /**
+     * HTTP status transport may use for response carrying this error. JSON-RPC error is still
+     * sent in body. Rate limited calls map to 429, other server and application errors to 500.
+     * */
+    pub fn suggested_http_status(&self) -> u16 {
+        match self.get_code() {
+            -32700 | -32600 | -32602 => 400,
+            -32601 => 404,
+            -32029 => 429,
+            _ => 500,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acceptable_content_type() {
+        for header in &["", "application/json", "application/json-rpc", "text/html, */*;q=0.8",
+                        "Application/JSON; charset=utf-8", "application/*"] {
+            assert!(acceptable_content_type(header), "{}", header);
+        }
+        for header in &["text/html", "application/xml, text/*", "application/json;q=0"] {
+            assert!(!acceptable_content_type(header), "{}", header);
+        }
+    }
+
+    #[test]
+    fn test_response_status() {
+        assert_eq!(200, response_status(Some("{}")));
+        assert_eq!(204, response_status(None));
+    }
+
+    #[test]
+    fn test_suggested_http_status() {
+        assert_eq!(400, ErrorCode::ParseError.suggested_http_status());
+        assert_eq!(400, ErrorCode::InvalidRequest.suggested_http_status());
+        assert_eq!(404, ErrorCode::MethodNotFound.suggested_http_status());
+        assert_eq!(400, ErrorCode::InvalidParams.suggested_http_status());
+        assert_eq!(500, ErrorCode::InternalError.suggested_http_status());
+        assert_eq!(500, ErrorCode::ServerError(-32000, "Busy").suggested_http_status());
+        assert_eq!(429, ErrorCode::ServerError(-32029, "rate limited").suggested_http_status());
+        assert_eq!(500, ErrorCode::AppError(1001, "Insufficient funds".to_owned()).suggested_http_status());
+    }
+}
@@ -0,0 +1,243 @@
+//! Detecting replayed requests for at-most-once processing.
+use rustc_serialize::json::Json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use {Deprecation, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest, StreamingHandlerResult};
+
+/**
+ * Outcome of call returned again for replayed id.
+ * */
+type Outcome = Result<HandlerResult, ErrorJsonRpc>;
+
+/**
+ * Call remembered under its id.
+ * */
+struct Call {
+    expires: Instant,
+    method: String,
+    params: Option<Json>,
+    outcome: Outcome,
+}
+
+/**
+ * Calls remembered within window, with their ids in order of expiry (which is order of calls,
+ * as window is fixed).
+ * */
+#[derive(Default)]
+struct Seen {
+    calls: HashMap<String, Call>,
+    expiry: VecDeque<(Instant, String)>,
+}
+
+impl Seen {
+    /**
+     * Forget calls whose window has passed.
+     * */
+    fn prune(&mut self, now: Instant) {
+        while self.expiry.front().is_some_and(|&(expires, _)| expires <= now) {
+            let (expires, id) = self.expiry.pop_front().unwrap();
+            // Id may have been used again after its window
+            if self.calls.get(&id).is_some_and(|call| call.expires == expires) {
+                self.calls.remove(&id);
+            }
+        }
+    }
+}
+
+/**
+ * Handler processing each request id at most once within time window. Replayed call (same id,
+ * method and params) gets outcome of the first one (or `ServerError(-32000, "duplicate id")`
+ * if `reject_replays` is set) without calling wrapped handler. Call reusing id with other
+ * method or params is always answered with that error. Notifications are passed through.
+ * */
+pub struct IdempotencyHandler<H> {
+    inner: H,
+    window: Duration,
+    reject: bool,
+    seen: Mutex<Seen>,
+}
+
+impl<H> IdempotencyHandler<H> {
+    /**
+     * Wrap handler, remembering ids for given time.
+     * */
+    pub fn new(inner: H, window: Duration) -> IdempotencyHandler<H> {
+        IdempotencyHandler {
+            inner,
+            window,
+            reject: false,
+            seen: Mutex::new(Seen::default()),
+        }
+    }
+
+    /**
+     * Answer replayed calls with error instead of outcome of the first call.
+     * */
+    pub fn reject_replays(mut self) -> IdempotencyHandler<H> {
+        self.reject = true;
+        self
+    }
+
+    /**
+     * Get wrapped handler reference
+     * */
+    pub fn get_inner(&self) -> &H {
+        &self.inner
+    }
+
+    /**
+     * Get mutable wrapped handler reference
+     * */
+    pub fn get_inner_mut(&mut self) -> &mut H {
+        &mut self.inner
+    }
+}
+
+/**
+ * Error answering call whose id was already used.
+ * */
+fn duplicate_id() -> ErrorJsonRpc {
+    ErrorJsonRpc::new(ErrorCode::ServerError(-32000, "duplicate id"))
+}
+
+impl<H: Handler> Handler for IdempotencyHandler<H> {
+    type Context = H::Context;
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Outcome {
+        let key = match req.id {
+            Some(id) => id.to_string(),
+            None => return self.inner.handle_outcome(req, custom),
+        };
+        {
+            let seen = self.seen.lock().unwrap();
+            if let Some(call) = seen.calls.get(&key).filter(|call| Instant::now() < call.expires) {
+                if call.method != req.method || call.params.as_ref() != req.params {
+                    warn!("Id {} of call of '{}' reused for other call of '{}'", key, call.method, req.method);
+                    return Err(duplicate_id());
+                }
+                warn!("Replayed call of '{}' with id {}", req.method, key);
+                if self.reject {
+                    return Err(duplicate_id());
+                }
+                return call.outcome.clone();
+            }
+        }
+
+        // Lock is not held while calling handler, so concurrent replays may both pass
+        let outcome = self.inner.handle_outcome(req, custom);
+        let mut seen = self.seen.lock().unwrap();
+        let now = Instant::now();
+        seen.prune(now);
+        let call = Call {
+            expires: now + self.window,
+            method: req.method.to_string(),
+            params: req.params.cloned(),
+            outcome: outcome.clone(),
+        };
+        seen.expiry.push_back((call.expires, key.clone()));
+        seen.calls.insert(key, call);
+        outcome
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Outcome {
+        if req.id.is_none() {
+            return self.inner.handle_streaming(req, custom, sink);
+        }
+        // Replayed outcome must be whole, so result is collected instead
+        self.handle_outcome(req, custom)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.inner.deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.inner.has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.inner.is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.inner.requires_response(method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use tests::Counter;
+    use JsonRpcServer;
+
+    #[test]
+    fn test_replayed_id() {
+        let handler = IdempotencyHandler::new(Counter::default(), Duration::from_secs(60));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        for _ in 0..2 {
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        }
+        assert_eq!(1, server.get_handler().get_inner().calls.load(Ordering::SeqCst));
+
+        // String id "1" is different id
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"id\": \"1\"}");
+        // Notifications are not tracked
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"charge\"}");
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"charge\"}");
+        assert_eq!(4, server.get_handler().get_inner().calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_rejected_replay() {
+        let handler = IdempotencyHandler::new(Counter::default(), Duration::from_secs(60)).reject_replays();
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"id\": 1}";
+        server.handle_request(request);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \
+                                                \"message\": \"duplicate id\"}, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        // Id may be reused after window
+        let handler = IdempotencyHandler::new(Counter::default(), Duration::from_secs(0)).reject_replays();
+        let server = JsonRpcServer::new_handler(handler);
+        server.handle_request(request);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+
+    #[test]
+    fn test_reused_id() {
+        let handler = IdempotencyHandler::new(Counter::default(), Duration::from_secs(60));
+        let server = JsonRpcServer::new_handler(handler);
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"params\": [10], \"id\": 1}");
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32000, \
+                                                \"message\": \"duplicate id\"}, \"id\": 1}");
+        for request in &["{\"jsonrpc\": \"2.0\", \"method\": \"refund\", \"params\": [10], \"id\": 1}",
+                         "{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"params\": [20], \"id\": 1}"] {
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        }
+        assert_eq!(1, server.get_handler().get_inner().calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_expired_pruned() {
+        let handler = IdempotencyHandler::new(Counter::default(), Duration::from_secs(0));
+        let server = JsonRpcServer::new_handler(handler);
+        for id in 0..100 {
+            server.handle_request(&format!("{{\"jsonrpc\": \"2.0\", \"method\": \"charge\", \"id\": {}}}", id));
+        }
+        assert_eq!(1, server.get_handler().seen.lock().unwrap().calls.len());
+    }
+}
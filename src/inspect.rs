@@ -0,0 +1,256 @@
+//! Inspecting requests without dispatching them.
+use rustc_serialize::json::{Json, ParserError};
+use std::fmt;
+
+use {validate_request, ErrorCode, ErrorJsonRpc, InternalErrorCode};
+
+/**
+ * Shape of incoming request.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /**
+     * Single request object.
+     * */
+    Single,
+    /**
+     * Batch with given number of elements.
+     * */
+    Batch(usize),
+    /**
+     * Valid JSON, but neither object nor array.
+     * */
+    Invalid,
+}
+
+/**
+ * Check if request is a batch. Request is parsed once, the same way as for dispatch.
+ * */
+pub fn classify_request(request: &str) -> Result<RequestKind, ParserError> {
+    Ok(match Json::from_str(request)? {
+        Json::Object(_) => RequestKind::Single,
+        Json::Array(ref a) => RequestKind::Batch(a.len()),
+        _ => RequestKind::Invalid,
+    })
+}
+
+/**
+ * Get method names of all requests, in order. Single request is treated as batch with one
+ * element. Elements without string method are reported as None, so none of them goes unnoticed.
+ * */
+pub fn batch_methods(request: &str) -> Result<Vec<Option<String>>, ParserError> {
+    let method = |r: &Json| r.find("method").and_then(Json::as_string).map(str::to_owned);
+    Ok(match Json::from_str(request)? {
+        Json::Array(ref a) => a.iter().map(method).collect(),
+        ref single => vec![method(single)],
+    })
+}
+
+/**
+ * Split requests into notifications and calls, keeping their order. Single request is treated
+ * as batch with one element. Elements which are not objects are calls, as they are answered
+ * with error.
+ * */
+pub fn partition_batch(request: &str) -> Result<(Vec<Json>, Vec<Json>), ParserError> {
+    let requests = match Json::from_str(request)? {
+        Json::Array(a) => a,
+        single => vec![single],
+    };
+    Ok(requests.into_iter().partition(|r| r.as_object().is_some_and(|o| !o.contains_key("id"))))
+}
+
+/**
+ * Error returned when id of request could not be determined.
+ * */
+#[derive(Debug)]
+pub enum PeekIdError {
+    /**
+     * Request is not valid JSON.
+     * */
+    Parse(ParserError),
+    /**
+     * Request is a batch, which has no single id.
+     * */
+    Batch,
+    /**
+     * Request is not an object or its id is an object.
+     * */
+    InvalidRequest,
+}
+
+impl fmt::Display for PeekIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PeekIdError::Parse(ref e) => write!(f, "invalid JSON: {}", e),
+            PeekIdError::Batch => write!(f, "batch request has no single id"),
+            PeekIdError::InvalidRequest => write!(f, "invalid request"),
+        }
+    }
+}
+
+impl ::std::error::Error for PeekIdError {}
+
+impl From<ParserError> for PeekIdError {
+    fn from(e: ParserError) -> PeekIdError {
+        PeekIdError::Parse(e)
+    }
+}
+
+/**
+ * Get id of single request without dispatching it. None for notification.
+ * Object ids are rejected, the same way as during dispatch.
+ * */
+pub fn peek_id(request: &str) -> Result<Option<Json>, PeekIdError> {
+    match Json::from_str(request)? {
+        Json::Object(mut members) => match members.remove("id") {
+            Some(Json::Object(_)) => Err(PeekIdError::InvalidRequest),
+            id => Ok(id),
+        },
+        Json::Array(_) => Err(PeekIdError::Batch),
+        _ => Err(PeekIdError::InvalidRequest),
+    }
+}
+
+/**
+ * Parse request, reporting failure like server does.
+ * */
+fn parse(request: &str) -> Result<Json, ErrorJsonRpc> {
+    Json::from_str(request).map_err(|e| InternalErrorCode::from(e).into_error())
+}
+
+/**
+ * Check that single request is well-formed JSON-RPC 2.0 request without executing it.
+ * Performs the same checks as server with default configuration and reports the same error.
+ * */
+pub fn verify_request(request: &str) -> Result<(), ErrorJsonRpc> {
+    let request = parse(request)?;
+    validate_request(&Default::default(), &request).map(|_| ()).map_err(InternalErrorCode::into_error)
+}
+
+/**
+ * Check each element of batch request like `verify_request`. Outer error is returned when
+ * request is not valid JSON or not a non-empty array.
+ * */
+pub fn verify_batch(request: &str) -> Result<Vec<Result<(), ErrorJsonRpc>>, ErrorJsonRpc> {
+    let options = Default::default();
+    match parse(request)? {
+        Json::Array(ref array) if !array.is_empty() => {
+            Ok(array.iter()
+                    .map(|r| validate_request(&options, r).map(|_| ()).map_err(InternalErrorCode::into_error))
+                    .collect())
+        }
+        _ => Err(ErrorJsonRpc::new(ErrorCode::InvalidRequest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_single() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 1}";
+        assert_eq!(RequestKind::Single, classify_request(request).unwrap());
+    }
+
+    #[test]
+    fn test_classify_batch() {
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}, 1,
+                        {\"jsonrpc\": \"2.0\", \"method\": \"notify\"}]";
+        assert_eq!(RequestKind::Batch(3), classify_request(request).unwrap());
+        assert_eq!(RequestKind::Batch(0), classify_request("[]").unwrap());
+        assert_eq!(RequestKind::Invalid, classify_request("\"foobar\"").unwrap());
+    }
+
+    #[test]
+    fn test_classify_malformed() {
+        assert!(classify_request("{\"jsonrpc\": \"2.0\", \"method\": \"foobar, \"params\": \"bar\", \"baz]")
+                    .is_err());
+    }
+
+    #[test]
+    fn test_batch_methods() {
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}, 1,
+                        {\"jsonrpc\": \"2.0\", \"method\": \"notify\"},
+                        {\"jsonrpc\": \"2.0\", \"method\": \"get_data\", \"id\": 9}]";
+        let expected = vec![Some("sum".to_owned()), None, Some("notify".to_owned()), Some("get_data".to_owned())];
+        assert_eq!(expected, batch_methods(request).unwrap());
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 1}";
+        assert_eq!(vec![Some("foobar".to_owned())], batch_methods(request).unwrap());
+        assert!(batch_methods("[{\"method\": ").is_err());
+    }
+
+    #[test]
+    fn test_peek_id() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 7}";
+        assert_eq!(Some(7), peek_id(request).unwrap().and_then(|id| id.as_i64()));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": \"abc\"}";
+        assert_eq!(Some(Json::String("abc".to_owned())), peek_id(request).unwrap());
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\"}";
+        assert_eq!(None, peek_id(request).unwrap());
+    }
+
+    #[test]
+    fn test_peek_id_invalid() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": {\"a\": 1}}";
+        match peek_id(request) {
+            Err(PeekIdError::InvalidRequest) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match peek_id("[{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 1}]") {
+            Err(PeekIdError::Batch) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match peek_id("{\"id\": ") {
+            Err(PeekIdError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_request() {
+        assert!(verify_request("{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": [1], \"id\": 1}").is_ok());
+        assert!(verify_request("{\"jsonrpc\": \"2.0\", \"method\": \"notify\"}").is_ok());
+
+        for &(request, code) in &[("{\"jsonrpc\": \"2.0\", \"method\": ", -32700),
+                                  ("{\"jsonrpc\": \"1.0\", \"method\": \"sum\", \"id\": 1}", -32600),
+                                  ("{\"jsonrpc\": \"2.0\", \"id\": 1}", -32600),
+                                  ("{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": {}}", -32600),
+                                  ("{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": 1}", -32600),
+                                  ("[{\"jsonrpc\": \"2.0\", \"method\": \"sum\"}]", -32600)] {
+            assert_eq!(Some(code), verify_request(request).err().map(|e| e.get_code()), "{}", request);
+        }
+        let error = verify_request("{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": 1}").unwrap_err();
+        assert_eq!(Some("params must be array or object"),
+                   error.get_data().and_then(|d| d.find("reason")).and_then(Json::as_string));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let results = verify_batch("[{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}, 5]").unwrap();
+        assert_eq!(2, results.len());
+        assert!(results[0].is_ok());
+        assert_eq!(Some(-32600), results[1].as_ref().err().map(ErrorJsonRpc::get_code));
+
+        assert_eq!(Some(-32600), verify_batch("[]").err().map(|e| e.get_code()));
+        assert_eq!(Some(-32700), verify_batch("[").err().map(|e| e.get_code()));
+    }
+
+    #[test]
+    fn test_partition_batch() {
+        let (notifications, calls) = partition_batch("[{\"jsonrpc\": \"2.0\", \"method\": \"log\", \"params\": [1]}, \
+                                                      {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}, \
+                                                      {\"jsonrpc\": \"2.0\", \"method\": \"log\", \"params\": [2]}, \
+                                                      5]")
+                                         .unwrap();
+        assert_eq!(vec![Some(1), Some(2)], notifications.iter().map(|n| n["params"][0].as_i64()).collect::<Vec<_>>());
+        assert_eq!(2, calls.len());
+        assert_eq!(Some(1), calls[0].find("id").and_then(Json::as_i64));
+        assert_eq!(Some(5), calls[1].as_i64());
+
+        let (notifications, calls) = partition_batch("{\"jsonrpc\": \"2.0\", \"method\": \"log\"}").unwrap();
+        assert_eq!((1, 0), (notifications.len(), calls.len()));
+        assert!(partition_batch("[").is_err());
+    }
+}
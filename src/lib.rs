@@ -1,13 +1,86 @@
 extern crate rustc_serialize;
+#[cfg(all(feature = "logging", not(feature = "tracing")))]
 #[macro_use]
 extern crate log;
-use rustc_serialize::json::{Json, ToJson, ParserError};
+// Events of `tracing` replace `log` records when both features are enabled
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+#[cfg(feature = "compression")]
+extern crate flate2;
+use rustc_serialize::json::{Decoder, DecoderError, ToJson, ParserError};
+// Re-exported for macros, so crates using them need not name `rustc_serialize` themselves
+pub use rustc_serialize::json::Json;
+use rustc_serialize::Decodable;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+#[cfg(not(any(feature = "logging", feature = "tracing")))]
+#[macro_use]
+mod nolog;
+#[macro_use]
+mod macros;
+
+mod batch;
+pub use batch::{BatchExecutor, BatchJob, ResponseBatch, ScopedThreads, NULL_ID_KEY};
+mod binary;
+pub use binary::{decode_bytes, respond_bytes};
+mod bounded;
+pub use bounded::BoundedHandler;
+mod cache;
+pub use cache::CachingHandler;
+mod catalog;
+pub use catalog::ErrorRegistry;
+mod client;
+mod coerce;
+pub use coerce::{Coercion, CoercionRegistry};
+pub use client::{forward_response, parse_batch_response, IdGenerator, JsonRpcClientRequest, ParsedError,
+                 ParsedResponse, PendingCall, PendingCalls, ResponseParseError};
+mod context;
+pub use context::{Cancellable, CancellableHandler, CancellationToken, DeadlineHandler, RpcContext,
+                  SetCorrelationId};
+mod framing;
+pub use framing::FrameReader;
+mod handshake;
+pub use handshake::INITIALIZE_METHOD;
+mod history;
+use history::{Exchange, History};
+mod http;
+pub use http::{acceptable_content_type, response_status, RESPONSE_CONTENT_TYPE};
+mod idempotency;
+pub use idempotency::IdempotencyHandler;
+mod inspect;
+mod methods;
+pub use methods::{Deprecation, DuplicateMethod, HashMapWithMethods, MergePolicy};
+pub use inspect::{batch_methods, classify_request, partition_batch, peek_id, verify_batch, verify_request, PeekIdError,
+                  RequestKind};
+mod ratelimit;
+pub use ratelimit::{rate_limited, MethodRateLimiter, RateLimiter};
+mod router;
+mod schema;
+pub use schema::{matches_shape, validate_json_schema};
+mod streaming;
+pub use streaming::StreamingHandlerResult;
+pub use router::NamespaceRouter;
+mod value;
+pub use value::JsonValue;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "testing")]
+pub use testing::{assert_response_eq, TestClient};
+
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "compression")]
+pub use compression::{CompressionError, Encoding};
 
 /**
  * Enum with possible errors.
  * */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ErrorCode {
     /**
      * Request is not valid JSON.
@@ -34,6 +107,59 @@ pub enum ErrorCode {
      * Error code should be between -32099 and -32000.
      * */
     ServerError(i32, &'static str),
+    /**
+     * Application defined error. Unlike `ServerError` any code is accepted and passed
+     * to client untouched, so it is up to application to avoid codes reserved by protocol
+     * (-32768 to -32000).
+     * */
+    AppError(i32, String),
+}
+
+/**
+ * Outcome of successful call (see `Handler::handle_outcome`).
+ * */
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerResult {
+    /**
+     * Result sent back to client.
+     * */
+    Value(Json),
+    /**
+     * Nothing to return, but call is still answered. Sent as `null` result, or without `result`
+     * member at all when server is configured with `set_omit_ack_result` (which is not allowed
+     * by specification).
+     * */
+    Ack,
+    /**
+     * Call is answered in other way (eg. later push over the same connection), so nothing is
+     * sent back, as if request was notification. This is not allowed by specification, client
+     * must be prepared for it.
+     * */
+    Suppress,
+    /**
+     * Result was pushed element by element into sink given to `Handler::handle_streaming`,
+     * so it is already on its way to client.
+     * */
+    Streamed,
+}
+
+impl HandlerResult {
+    /**
+     * Plain result, for callers not aware of other outcomes. Ack, Suppress and Streamed
+     * become `null`.
+     * */
+    pub fn into_json(self) -> Json {
+        match self {
+            HandlerResult::Value(result) => result,
+            HandlerResult::Ack | HandlerResult::Suppress | HandlerResult::Streamed => Json::Null,
+        }
+    }
+}
+
+impl From<Json> for HandlerResult {
+    fn from(result: Json) -> HandlerResult {
+        HandlerResult::Value(result)
+    }
 }
 
 /**
@@ -42,6 +168,99 @@ pub enum ErrorCode {
 pub trait Handler {
     type Context;
     fn handle(&self, reg: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc>;
+
+    /**
+     * Process request, telling server how to answer it. This is what server calls. By default
+     * result of `handle` is sent back. Handlers returning other outcomes (and wrappers, so such
+     * outcomes pass through them) override it.
+     * */
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        self.handle(req, custom).map(HandlerResult::Value)
+    }
+
+    /**
+     * Process request whose result may be pushed into `sink` element by element, answering
+     * `HandlerResult::Streamed` then. Called instead of `handle_outcome` by
+     * `JsonRpcServer::handle_request_streaming`. By default no method streams its result.
+     * */
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        _sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        self.handle_outcome(req, custom)
+    }
+
+    /**
+     * Deprecation notice of method. By default no method is deprecated.
+     * */
+    fn deprecation(&self, _method: &str) -> Option<&Deprecation> {
+        None
+    }
+
+    /**
+     * Check if method can be handled, without calling it. By default every method is assumed
+     * to exist.
+     * */
+    fn has_method(&self, _method: &str) -> bool {
+        true
+    }
+
+    /**
+     * Check if method is registered as notification-only, so its result is never sent back.
+     * By default no method is.
+     * */
+    fn is_notification_only(&self, _method: &str) -> bool {
+        false
+    }
+
+    /**
+     * Check if method must not be called as notification, because its result matters.
+     * By default no method is.
+     * */
+    fn requires_response(&self, _method: &str) -> bool {
+        false
+    }
+}
+
+/**
+ * Type erased handler, allowing servers with different handlers to be stored together.
+ * */
+pub type BoxedHandler<C> = Box<dyn Handler<Context = C> + Sync + Send>;
+
+impl<C> Handler for BoxedHandler<C> {
+    type Context = C;
+    fn handle(&self, req: &JsonRpcRequest, custom: &C) -> Result<Json, ErrorJsonRpc> {
+        (**self).handle(req, custom)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &C) -> Result<HandlerResult, ErrorJsonRpc> {
+        (**self).handle_outcome(req, custom)
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &C,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        (**self).handle_streaming(req, custom, sink)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        (**self).deprecation(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        (**self).has_method(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        (**self).is_notification_only(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        (**self).requires_response(method)
+    }
 }
 
 /**
@@ -60,6 +279,16 @@ enum InternalErrorCode {
 }
 
 impl InternalErrorCode {
+    /**
+     * Converts InternalErrorCode to ErrorJsonRpc, dropping id.
+     * */
+    fn into_error(self) -> ErrorJsonRpc {
+        let (error, data) = match self {
+            InternalErrorCode::WithId(err, _, data) | InternalErrorCode::WithoutId(err, data) => (err, data),
+        };
+        ErrorJsonRpc { error, data }
+    }
+
     /**
      * Converts InternalErrorCode to JsonRpcResponse.
      * */
@@ -73,12 +302,41 @@ impl InternalErrorCode {
     }
 }
 
+/**
+ * Description of predefined error code, generic one for other codes.
+ * */
+fn default_desc(code: i32) -> &'static str {
+    match code {
+        -32700 => "Parse error",
+        -32600 => "Invalid Request",
+        -32601 => "Method not found",
+        -32602 => "Invalid params",
+        -32603 => "Internal error",
+        _ => "Server error",
+    }
+}
+
 // Convinient method for getting integer value for error
 impl ErrorCode {
+    /**
+     * Map error code back to enum. Codes other than predefined ones are converted
+     * to `ServerError` with generic description.
+     * */
+    pub fn from_code(code: i32) -> ErrorCode {
+        match code {
+            -32700 => ErrorCode::ParseError,
+            -32600 => ErrorCode::InvalidRequest,
+            -32601 => ErrorCode::MethodNotFound,
+            -32602 => ErrorCode::InvalidParams,
+            -32603 => ErrorCode::InternalError,
+            x => ErrorCode::ServerError(x, "Server error"),
+        }
+    }
+
     /**
      * Retrieve error code.
      * */
-    fn get_code(&self) -> i32 {
+    pub fn get_code(&self) -> i32 {
         match *self {
             ErrorCode::ParseError => -32700,
             ErrorCode::InvalidRequest => -32600,
@@ -86,20 +344,21 @@ impl ErrorCode {
             ErrorCode::InvalidParams => -32602,
             ErrorCode::InternalError => -32603,
             ErrorCode::ServerError(x, _) => x,
+            ErrorCode::AppError(x, _) => x,
         }
     }
 
     /**
-     * Get short description for error.
+     * Get short description for error. Empty custom description is replaced with default
+     * one for the code, as some clients reject empty messages.
      * */
-    fn get_desc(&self) -> &'static str {
+    pub fn get_desc(&self) -> &str {
         match *self {
-            ErrorCode::ParseError => "Parse error",
-            ErrorCode::InvalidRequest => "Invalid Request",
-            ErrorCode::MethodNotFound => "Method not found",
-            ErrorCode::InvalidParams => "Invalid params",
-            ErrorCode::InternalError => "Internal error",
+            ErrorCode::ServerError(x, "") => default_desc(x),
+            ErrorCode::AppError(x, ref s) if s.is_empty() => default_desc(x),
             ErrorCode::ServerError(_, s) => s,
+            ErrorCode::AppError(_, ref s) => s,
+            _ => default_desc(self.get_code()),
         }
     }
 
@@ -110,7 +369,7 @@ impl ErrorCode {
     fn is_valid(&self) -> bool {
         match *self {
             // Error code is only valid within that range
-            ErrorCode::ServerError(-32099...-32000, _) => true,
+            ErrorCode::ServerError(-32099..=-32000, _) => true,
             // All remaining ServerError enums are invalid
             ErrorCode::ServerError(_, _) => false,
             // All predefined codes are valid
@@ -124,9 +383,9 @@ impl ErrorCode {
  * */
 pub struct JsonRpcRequest<'a> {
     /**
-     * Name of remote procedure to call.
+     * Name of remote procedure to call. Owned only when changed by method normalizer.
      * */
-    pub method: &'a str,
+    pub method: Cow<'a, str>,
 
     /**
      * Parameters to method. Only Object (request by position) or Array (request by name).
@@ -139,12 +398,170 @@ pub struct JsonRpcRequest<'a> {
      * This should remain provate field.
      * */
     id: Option<&'a Json>,
+
+    /**
+     * Whole request object, including non-standard members.
+     * */
+    raw: Option<&'a Json>,
+}
+
+/**
+ * Parameters of request after resolving `__positional` convention.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Params<'a> {
+    /**
+     * Parameters passed by position.
+     * */
+    Positional(&'a rustc_serialize::json::Array),
+    /**
+     * Parameters passed by name.
+     * */
+    Named(&'a rustc_serialize::json::Object),
+    /**
+     * Request has no parameters.
+     * */
+    Empty,
+}
+
+/**
+ * Member of named params carrying positional params instead.
+ * */
+const POSITIONAL_MEMBER: &str = "__positional";
+
+/**
+ * Convert parameter to requested type, reporting Invalid params on failure.
+ * */
+fn typed_param<'a, T, F>(param: Option<&'a Json>, name: &str, expected: &str, convert: F)
+                         -> Result<T, ErrorJsonRpc>
+    where F: Fn(&'a Json) -> Option<T>
+{
+    match param {
+        Some(value) => {
+            convert(value).ok_or_else(|| {
+                ErrorJsonRpc::new_data(ErrorCode::InvalidParams,
+                                       format!("parameter {} must be {}", name, expected).to_json())
+            })
+        }
+        None => {
+            Err(ErrorJsonRpc::new_data(ErrorCode::InvalidParams,
+                                       format!("missing parameter {}", name).to_json()))
+        }
+    }
+}
+
+impl<'a> JsonRpcRequest<'a> {
+    /**
+     * Get whole request object as received, eg. to read vendor extension members.
+     * */
+    pub fn raw(&self) -> Option<&'a Json> {
+        self.raw
+    }
+
+    /**
+     * Get integer id or string id containing integer (eg. `"123"`) for internal correlation.
+     * Response still carries id exactly as received.
+     * */
+    pub fn id_as_i64(&self) -> Option<i64> {
+        match self.id {
+            Some(Json::String(id)) => id.parse().ok(),
+            Some(id) => id.as_i64(),
+            None => None,
+        }
+    }
+
+    /**
+     * Get parameters, treating `__positional` array inside named parameters (sent by some
+     * clients for methods preferring positional access) as positional parameters.
+     * */
+    pub fn positional_or_named(&self) -> Params<'a> {
+        match self.params {
+            Some(Json::Array(array)) => Params::Positional(array),
+            Some(Json::Object(object)) => match object.get(POSITIONAL_MEMBER) {
+                Some(Json::Array(array)) => Params::Positional(array),
+                _ => Params::Named(object),
+            },
+            _ => Params::Empty,
+        }
+    }
+
+    /**
+     * Get positional parameter. None if parameters are not passed by position or index is
+     * out of range.
+     * */
+    pub fn param_at(&self, index: usize) -> Option<&'a Json> {
+        self.params.and_then(|p| p.as_array()).and_then(|a| a.get(index))
+    }
+
+    /**
+     * Get named parameter. None if parameters are not passed by name or there is
+     * no such parameter.
+     * */
+    pub fn param_named(&self, name: &str) -> Option<&'a Json> {
+        self.params.and_then(|p| p.as_object()).and_then(|o| o.get(name))
+    }
+
+    /**
+     * Get positional parameter as string.
+     * */
+    pub fn param_at_as_str(&self, index: usize) -> Result<&'a str, ErrorJsonRpc> {
+        typed_param(self.param_at(index), &index.to_string(), "string", Json::as_string)
+    }
+
+    /**
+     * Get positional parameter as integer.
+     * */
+    pub fn param_at_as_i64(&self, index: usize) -> Result<i64, ErrorJsonRpc> {
+        typed_param(self.param_at(index), &index.to_string(), "integer", Json::as_i64)
+    }
+
+    /**
+     * Get positional parameter as number.
+     * */
+    pub fn param_at_as_f64(&self, index: usize) -> Result<f64, ErrorJsonRpc> {
+        typed_param(self.param_at(index), &index.to_string(), "number", Json::as_f64)
+    }
+
+    /**
+     * Get positional parameter as boolean.
+     * */
+    pub fn param_at_as_bool(&self, index: usize) -> Result<bool, ErrorJsonRpc> {
+        typed_param(self.param_at(index), &index.to_string(), "boolean", Json::as_boolean)
+    }
+
+    /**
+     * Get named parameter as string.
+     * */
+    pub fn param_named_as_str(&self, name: &str) -> Result<&'a str, ErrorJsonRpc> {
+        typed_param(self.param_named(name), &format!("'{}'", name), "string", Json::as_string)
+    }
+
+    /**
+     * Get named parameter as integer.
+     * */
+    pub fn param_named_as_i64(&self, name: &str) -> Result<i64, ErrorJsonRpc> {
+        typed_param(self.param_named(name), &format!("'{}'", name), "integer", Json::as_i64)
+    }
+
+    /**
+     * Get named parameter as number.
+     * */
+    pub fn param_named_as_f64(&self, name: &str) -> Result<f64, ErrorJsonRpc> {
+        typed_param(self.param_named(name), &format!("'{}'", name), "number", Json::as_f64)
+    }
+
+    /**
+     * Get named parameter as boolean.
+     * */
+    pub fn param_named_as_bool(&self, name: &str) -> Result<bool, ErrorJsonRpc> {
+        typed_param(self.param_named(name), &format!("'{}'", name), "boolean", Json::as_boolean)
+    }
 }
 
 /**
  * Describe Error response
  * */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ErrorJsonRpc {
     /**
      * Error code
@@ -178,6 +595,22 @@ impl ErrorJsonRpc {
         }
     }
 
+    /**
+     * Create error reporting partial failure of bulk operation. Each item failure is
+     * described in `data` array by object with `code` and `message`.
+     * */
+    pub fn with_sub_errors(err: ErrorCode, sub_errors: Vec<(i32, String)>) -> ErrorJsonRpc {
+        let data = sub_errors.into_iter()
+                             .map(|(code, message)| {
+                                 let mut d = BTreeMap::new();
+                                 d.insert("code".to_owned(), code.to_json());
+                                 d.insert("message".to_owned(), message.to_json());
+                                 Json::Object(d)
+                             })
+                             .collect();
+        ErrorJsonRpc::new_data(err, Json::Array(data))
+    }
+
     /**
      * Get code for error
      * */
@@ -198,8 +631,45 @@ impl ErrorJsonRpc {
     pub fn get_data(&self) -> Option<&Json> {
         self.data.as_ref()
     }
+
+    /**
+     * Decode additional data into user type. None if there is no data.
+     * */
+    pub fn data_as<T: Decodable>(&self) -> Option<Result<T, DecoderError>> {
+        self.data.as_ref().map(|data| T::decode(&mut Decoder::new(data.clone())))
+    }
+}
+
+
+impl From<ErrorCode> for ErrorJsonRpc {
+    fn from(err: ErrorCode) -> ErrorJsonRpc {
+        ErrorJsonRpc::new(err)
+    }
+}
+
+impl From<DecoderError> for ErrorJsonRpc {
+    /**
+     * Params could not be decoded into expected type, reason is described in `data`.
+     * */
+    fn from(err: DecoderError) -> ErrorJsonRpc {
+        let reason = match err {
+            DecoderError::ExpectedError(expected, found) => format!("expected {}, found {}", expected, found),
+            DecoderError::MissingFieldError(field) => format!("missing field '{}'", field),
+            DecoderError::UnknownVariantError(variant) => format!("unknown variant '{}'", variant),
+            DecoderError::ApplicationError(reason) => reason,
+            DecoderError::ParseError(e) => format!("invalid JSON: {}", e),
+            DecoderError::EOF => "unexpected end of params".to_owned(),
+        };
+        ErrorJsonRpc::new_data(ErrorCode::InvalidParams, reason.to_json())
+    }
 }
 
+/**
+ * Shorthand for failing handler with error without data.
+ * */
+pub fn fail<T>(err: ErrorCode) -> Result<T, ErrorJsonRpc> {
+    Err(err.into())
+}
 
 impl ToJson for ErrorJsonRpc {
     /**
@@ -234,6 +704,26 @@ pub struct JsonRpcResponse {
      * Response id. Exactly match id from request. Value is None only for notification.
      * */
     id: Option<Json>,
+
+    /**
+     * Name of called method, echoed back as non-standard `method` member when enabled.
+     * */
+    method: Option<String>,
+
+    /**
+     * Time spent in handler, sent as non-standard `_timingMs` member when enabled.
+     * */
+    timing_ms: Option<u64>,
+
+    /**
+     * Deprecation notice of called method, sent as non-standard `_deprecation` member when enabled.
+     * */
+    deprecation: Option<Json>,
+
+    /**
+     * Result is intentionally omitted, as configured for acknowledgements.
+     * */
+    result_omitted: bool,
 }
 
 impl JsonRpcResponse {
@@ -246,13 +736,34 @@ impl JsonRpcResponse {
         } else {
             ErrorCode::InternalError
         };
+        // Notifications are never answered, so error is not stored
+        let error = id.as_ref().map(|_| match data {
+            Some(data) => ErrorJsonRpc::new_data(error, data),
+            None => ErrorJsonRpc::new(error),
+        });
         JsonRpcResponse {
             result: None,
-            error: match data {
-                Some(data) => Some(ErrorJsonRpc::new_data(error, data)),
-                None => Some(ErrorJsonRpc::new(error)),
-            },
-            id: id,
+            error,
+            id,
+            method: None,
+            timing_ms: None,
+            deprecation: None,
+            result_omitted: false,
+        }
+    }
+
+    /**
+     * Build empty response, which is never sent.
+     * */
+    fn suppressed() -> JsonRpcResponse {
+        JsonRpcResponse {
+            result: None,
+            error: None,
+            id: None,
+            method: None,
+            timing_ms: None,
+            deprecation: None,
+            result_omitted: false,
         }
     }
 
@@ -261,19 +772,98 @@ impl JsonRpcResponse {
      * */
     fn new_result(req: &JsonRpcRequest, data: Json) -> JsonRpcResponse {
         JsonRpcResponse {
-            result: Some(data),
+            // Notifications are never answered, so result is not stored
+            result: req.id.map(|_| data),
             error: None,
             id: req.id.cloned(),
+            method: None,
+            timing_ms: None,
+            deprecation: None,
+            result_omitted: false,
+        }
+    }
+
+    /**
+     * Build response with result, recording name of called method.
+     * */
+    fn new_result_with_method(req: &JsonRpcRequest, data: Json) -> JsonRpcResponse {
+        JsonRpcResponse {
+            method: Some(req.method.to_string()),
+            ..JsonRpcResponse::new_result(req, data)
+        }
+    }
+
+    /**
+     * Check invariant required by specification: response with id contains exactly one
+     * of result and error, notification contains none of them. The only exception is
+     * acknowledgement with result omitted on request.
+     * */
+    fn is_consistent(&self) -> bool {
+        match self.id {
+            Some(_) => (self.result.is_some() || self.result_omitted) != self.error.is_some(),
+            None => self.result.is_none() && self.error.is_none(),
+        }
+    }
+}
+
+impl JsonRpcResponse {
+    /**
+     * Serialize with envelope members in fixed order: `jsonrpc`, `result` or `error`, `id`,
+     * followed by non-standard members. Meant for clients parsing responses positionally.
+     * */
+    pub fn to_ordered_string(&self) -> String {
+        ordered_string(&self.to_json())
+    }
+}
+
+/**
+ * Envelope members in order used by `JsonRpcResponse::to_ordered_string`.
+ * */
+const ENVELOPE_ORDER: [&str; 4] = ["jsonrpc", "result", "error", "id"];
+
+/**
+ * Strip `jsonrpc` member from response (or each response in batch).
+ * */
+fn without_version(response: Json) -> Json {
+    match response {
+        Json::Object(mut members) => {
+            members.remove("jsonrpc");
+            Json::Object(members)
+        }
+        Json::Array(responses) => Json::Array(responses.into_iter().map(without_version).collect()),
+        other => other,
+    }
+}
+
+/**
+ * Serialize response (or batch of them) with envelope members in fixed order.
+ * */
+fn ordered_string(response: &Json) -> String {
+    match *response {
+        Json::Object(ref members) => {
+            let ordered = ENVELOPE_ORDER.iter()
+                                        .filter_map(|key| members.get_key_value(*key))
+                                        .chain(members.iter().filter(|&(k, _)| !ENVELOPE_ORDER.contains(&k.as_str())));
+            let members: Vec<String> = ordered.map(|(k, v)| format!("{}:{}", k.to_json(), v)).collect();
+            format!("{{{}}}", members.join(","))
         }
+        Json::Array(ref responses) => {
+            let responses: Vec<String> = responses.iter().map(ordered_string).collect();
+            format!("[{}]", responses.join(","))
+        }
+        ref other => other.to_string(),
     }
 }
 
 impl ToJson for JsonRpcResponse {
     /**
-     * Convert JsonRpcResponse to Json
+     * Convert JsonRpcResponse to Json.
+     * Result is always nested under `result` key, so members returned by handler
+     * (even `jsonrpc` or `id`) never replace envelope members.
      * */
     fn to_json(&self) -> Json {
-        if self.id == None {
+        debug_assert!(self.is_consistent(), "Malformed response");
+        if self.id.is_none() {
             return Json::Null;
         }
         let mut d = BTreeMap::new();
@@ -287,418 +877,3014 @@ impl ToJson for JsonRpcResponse {
         if let Some(ref id) = self.id {
             d.insert("id".to_owned(), id.clone());
         }
+        // Non-standard extension
+        if let Some(ref method) = self.method {
+            d.insert("method".to_owned(), method.to_json());
+        }
+        if let Some(timing_ms) = self.timing_ms {
+            d.insert("_timingMs".to_owned(), timing_ms.to_json());
+        }
+        if let Some(ref deprecation) = self.deprecation {
+            d.insert("_deprecation".to_owned(), deprecation.clone());
+        }
         Json::Object(d)
     }
 }
 
 /**
- * JSON-RPC processing unit.
+ * Members allowed in request object.
  * */
-pub struct JsonRpcServer<H: Handler + 'static> {
-    handler: H,
-}
-
-pub type HashMapWithMethods = HashMap<String, Box<Fn(&JsonRpcRequest) -> Result<Json, ErrorJsonRpc> + 'static + Sync + Send>>;
-impl Handler for HashMapWithMethods {
-    type Context = ();
-    fn handle(&self, req: &JsonRpcRequest, _: &Self::Context) -> Result<Json, ErrorJsonRpc> {
-        self.get(req.method)
-            .ok_or_else(|| {
-                error!("Requested method '{}' not found!", req.method);
-                ErrorJsonRpc::new(ErrorCode::MethodNotFound)
-            })
-            .and_then(|s| s(&req))
-    }
-}
+const REQUEST_MEMBERS: [&str; 4] = ["jsonrpc", "method", "params", "id"];
 
-impl JsonRpcServer<HashMapWithMethods> {
+/**
+ * What to do when handler returns `ServerError` with code outside of allowed range.
+ * Such error is always sent to client as `InternalError`.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidCodePolicy {
     /**
-     * Create new default instance of JsonRpcServer.
+     * Downgrade silently.
      * */
-    pub fn new() -> JsonRpcServer<HashMapWithMethods> {
-        JsonRpcServer { handler: Default::default() }
-    }
+    #[default]
+    Silent,
+    /**
+     * Downgrade and log warning with offending code.
+     * */
+    LogAndDowngrade,
+    /**
+     * Panic in debug builds, behave like `LogAndDowngrade` in release builds.
+     * */
+    Panic,
 }
 
-impl From<ParserError> for InternalErrorCode {
-    fn from(_: ParserError) -> InternalErrorCode {
-        InternalErrorCode::WithoutId(ErrorCode::ParseError, None)
-    }
+/**
+ * How calls to unknown methods are answered inside batch.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownMethodPolicy {
+    /**
+     * Answer with `MethodNotFound` error.
+     * */
+    #[default]
+    Error,
+    /**
+     * Omit call from batch response, as if it was notification.
+     * */
+    Ignore,
 }
-impl <H: Handler> JsonRpcServer<H> where H::Context: Default {
-    /// Specialized implementation for context implementing default trait
-    pub fn handle_request(&self, req: &str) -> Option<String> {
-        self.handle_request_context(req, &Default::default())
+
+/**
+ * Validation failures of single request.
+ * */
+struct Violations {
+    messages: Vec<String>,
+    collect: bool,
+}
+
+impl Violations {
+    fn new(collect: bool) -> Violations {
+        Violations {
+            messages: Vec::new(),
+            collect,
+        }
+    }
+
+    /**
+     * Record violation. Returns true if validation should stop.
+     * */
+    fn add<S: Into<String>>(&mut self, message: S) -> bool {
+        self.messages.push(message.into());
+        !self.collect
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /**
+     * Convert to Invalid Request error. Messages are attached only when collecting.
+     * */
+    fn into_error(self) -> InternalErrorCode {
+        let data = if self.collect {
+            Some(self.messages.to_json())
+        } else {
+            None
+        };
+        InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, data)
     }
 }
-impl <H: Handler> JsonRpcServer<H> {
+
+/**
+ * Lets through only every n-th of frequent log events, so flood of invalid requests
+ * can't flood logs too.
+ * */
+#[derive(Default)]
+struct LogSampler {
+    rate: usize,
+    seen: AtomicUsize,
+}
+
+impl LogSampler {
     /**
-     * Create instance of JsonRpcServer with custom handler
+     * Count event and check if it should be logged. First event is always logged.
      * */
-    pub fn new_handler(h: H) -> JsonRpcServer<H> {
-        JsonRpcServer { handler: h }
+    fn sample(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        self.rate <= 1 || seen.is_multiple_of(self.rate)
     }
+}
 
-    fn _handle_single(&self,
-                      req: &rustc_serialize::json::Object,
-                      custom: &H::Context)
-                      -> Result<JsonRpcResponse, InternalErrorCode> {
+/**
+ * Configuration of JsonRpcServer.
+ * */
+#[derive(Default)]
+struct ServerOptions {
+    /**
+     * Reject requests containing members not defined by specification.
+     * */
+    strict: bool,
 
-        // Ensure field jsonrpc exist and contains string "2.0"
-        if !req.get("jsonrpc")
-               .and_then(|o| o.as_string())
-               .map_or(false, |s| s == "2.0") {
-            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
-        }
+    /**
+     * Maximal length of request in bytes. None for no limit.
+     * */
+    max_request_bytes: Option<usize>,
+
+    /**
+     * Maximal length of serialized response in bytes. None for no limit.
+     * */
+    max_response_bytes: Option<usize>,
 
-        // try parse ID and then pass it to error message
-        let request_id = req.get("id");
+    /**
+     * Maximal number of requests in single batch. None for no limit.
+     * */
+    max_batch_size: Option<usize>,
 
-        if let Some(&Json::Object(_)) = request_id {
-            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
-        }
+    /**
+     * Echo method name in successful responses.
+     * */
+    echo_method: bool,
 
-        // At this point we know assigned id
-        let request_method = if let Some(s) = req.get("method").and_then(|m| m.as_string()) {
-            s
-        } else {
-            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
-        };
+    /**
+     * Reject requests with boolean id.
+     * */
+    reject_bool_id: bool,
 
-        let request_params = match req.get("params") {
-            Some(json) => match *json {
-                Json::Array(_) | Json::Object(_) => Some(json),
-                Json::Null => None,
-                _ => return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None)),
-            },
-            None => None,
-        };
+    /**
+     * Key under which successful results are wrapped. None to leave results intact.
+     * */
+    result_wrapper: Option<&'static str>,
 
-        // From now request is considered as VALID and code should use WithId
-        let request = JsonRpcRequest {
-            method: request_method,
-            params: request_params,
-            id: request_id,
-        };
+    /**
+     * Report all validation failures instead of failing on first one.
+     * */
+    collect_validation_errors: bool,
 
-        self.handler
-            .handle(&request, custom)
-            .map(|s| JsonRpcResponse::new_result(&request, s))
-            .map_err(move |e| {
-                InternalErrorCode::WithId(e.error, request.id.cloned(), e.data)
-            })
+    /**
+     * Accept request encoded once more as JSON string.
+     * */
+    allow_double_encoded: bool,
+
+    /**
+     * Serialize responses in human readable form.
+     * */
+    pretty: bool,
+
+    /**
+     * Report time spent in handler.
+     * */
+    include_timing: bool,
+
+    /**
+     * Send acknowledgements without `result` member.
+     * */
+    omit_ack_result: bool,
+
+    /**
+     * Acknowledge notifications in batch.
+     * */
+    ack_notifications: bool,
+
+    /**
+     * Answer with stub result instead of calling handler.
+     * */
+    dry_run: bool,
+
+    /**
+     * Leave `jsonrpc` member out of responses.
+     * */
+    omit_version_field: bool,
+
+    /**
+     * Sampling of invalid request and unknown method log events.
+     * */
+    log_sampler: LogSampler,
+
+    /**
+     * Reject calls until `initialize` handshake is done.
+     * */
+    require_initialize: bool,
+
+    /**
+     * Reaction to invalid error codes returned by handler.
+     * */
+    invalid_code_policy: InvalidCodePolicy,
+
+    /**
+     * Reaction to unknown methods called in batch.
+     * */
+    unknown_method_policy: UnknownMethodPolicy,
+
+    /**
+     * Attach deprecation notices to responses.
+     * */
+    report_deprecation: bool,
+
+    /**
+     * Serialize envelope members in fixed order.
+     * */
+    ordered_output: bool,
+}
+
+/**
+ * Callback deciding if request may be passed to handler. Returned error is sent to client
+ * instead of calling handler.
+ * */
+pub type Authorizer<C> = Box<dyn Fn(&JsonRpcRequest, &C) -> Result<(), ErrorJsonRpc> + 'static + Sync + Send>;
+
+/**
+ * Callback normalizing method name before dispatch.
+ * */
+pub type MethodNormalizer = Box<dyn for<'a> Fn(&'a str) -> Cow<'a, str> + 'static + Sync + Send>;
+
+/**
+ * Callback mapping public method name to registered one (eg. `v1.subtract` to `subtract`).
+ * */
+pub type MethodRewriter = Box<dyn for<'a> Fn(&'a str) -> Cow<'a, str> + 'static + Sync + Send>;
+
+/**
+ * Callback notified about each generated error response with error code, method (if known) and id.
+ * */
+pub type ErrorObserver = Box<dyn Fn(i32, Option<&str>, Option<&Json>) + 'static + Sync + Send>;
+
+/**
+ * Callback checking if method name is acceptable.
+ * */
+pub type MethodValidator = Box<dyn Fn(&str) -> bool + 'static + Sync + Send>;
+
+/**
+ * Callback reshaping successful result of method.
+ * */
+pub type ResultTransform = Box<dyn Fn(Json) -> Json + 'static + Sync + Send>;
+
+/**
+ * Callback receiving exact text of each response sent back.
+ * */
+pub type ResponseAudit = Box<dyn Fn(&str) + 'static + Sync + Send>;
+
+/**
+ * Function passing request id to context.
+ * */
+type IdPropagator<C> = fn(&C, Option<&Json>);
+
+/**
+ * Check request up to the point of dispatch, returning method, params and id.
+ * */
+fn validate_request<'a>(options: &ServerOptions,
+                        req: &'a Json)
+                        -> Result<(&'a str, Option<&'a Json>, Option<&'a Json>), InternalErrorCode> {
+    let mut violations = Violations::new(options.collect_validation_errors);
+
+    // Request must be an object, nothing more can be checked otherwise
+    if !JsonValue::is_object(req) {
+        violations.add("request must be an object");
+        return Err(violations.into_error());
     }
 
-    fn _handle_multiple(&self,
-                        array: &rustc_serialize::json::Array,
-                        custom: &H::Context)
-                        -> Result<Option<Json>, InternalErrorCode> {
-        if array.is_empty() {
-            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
+    // Ensure field jsonrpc exist and contains string "2.0"
+    if req.get("jsonrpc").and_then(JsonValue::as_str) != Some("2.0") &&
+       violations.add("'jsonrpc' must be \"2.0\"") {
+        return Err(violations.into_error());
+    }
+
+    if options.strict {
+        for member in req.keys().into_iter().filter(|k| !REQUEST_MEMBERS.contains(k)) {
+            if options.log_sampler.sample() {
+                error!("Unexpected member '{}' in request", member);
+            }
+            if violations.add(format!("unexpected member '{}'", member)) {
+                return Err(violations.into_error());
+            }
         }
+    }
 
-        // Convert to vector (required by json api)
-        let response_vector: Vec<_> = array.iter()
-                .filter_map(|request| {
-                    info!("Processing {}", request);
-                    let response = request.as_object()
-                            // Convert None to error
-                            .ok_or_else(|| InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None))
-                            // Invoke remote procedure
-                            .and_then(|o|self._handle_single(o, custom))
-                            // Convert any error to Json
-                            .unwrap_or_else(|e|e.into_response());
-                            // Skip notifications in response
-                            if response.id == None {
-                                None
-                            } else {
-                                Some(response)
-                            }
-                }).collect();
+    // try parse ID and then pass it to error message
+    let request_id = req.get("id");
 
-        // All notifications nothing to respond
-        if response_vector.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(response_vector.to_json()))
-        }
+    if request_id.is_some_and(JsonValue::is_object) && violations.add("'id' must not be an object") {
+        return Err(violations.into_error());
     }
 
-    fn _handle_request(&self,
-                       request: &str,
-                       custom: &H::Context) -> Result<Option<Json>, InternalErrorCode> {
-        let request_json = try!(Json::from_str(&request));
+    if options.reject_bool_id && request_id.is_some_and(JsonValue::is_boolean) &&
+       violations.add("'id' must not be a boolean") {
+        return Err(violations.into_error());
+    }
 
-        // for now only plain object support
-        match request_json {
-            Json::Object(ref s) => self._handle_single(s, custom).map(|m| Some(m.to_json())),
-            Json::Array(ref a) => self._handle_multiple(a, custom),
-            _ => Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None)),
-        }
+    // Empty method name can't refer to any method
+    let request_method = req.get("method")
+                            .and_then(JsonValue::as_str)
+                            .filter(|m| !m.trim().is_empty());
+    if request_method.is_none() && violations.add("'method' must be a non-empty string") {
+        return Err(violations.into_error());
     }
 
-    pub fn handle_request_context(&self, request: &str, custom: &H::Context) -> Option<String> {
-        let result = self._handle_request(&request, custom);
-        match result {
-            Ok(Some(ref resp)) if *resp != Json::Null => Some(resp.to_json().to_string()),
-            // Notification (but got some data?), no returned response anyway
-            Ok(Some(ref some)) => {
-                warn!("Co to jest?: {:?}", some);
-                None
-            }
-            Ok(_) => None,
-            Err(err) => {
-                let response = err.into_response().to_json();
-                if response == Json::Null {
-                    println!("Empty");
-                    None
-                } else {
-                    Some(response.to_string())
-                }
+    let request_params = match req.get("params") {
+        Some(json) if JsonValue::is_array(json) || JsonValue::is_object(json) => Some(json),
+        Some(json) if JsonValue::is_null(json) => None,
+        Some(_) => {
+            if violations.add("'params' must be an array or object") {
+                let data = reason_data("params must be array or object");
+                return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, Some(data)));
             }
+            None
         }
+        None => None,
+    };
+
+    match request_method {
+        Some(method) if violations.is_empty() => Ok((method, request_params, request_id)),
+        _ => Err(violations.into_error()),
     }
+}
+
+/**
+ * JSON-RPC processing unit.
+ * */
+pub struct JsonRpcServer<H: Handler + 'static> {
+    handler: H,
+    options: ServerOptions,
+    authorizer: Option<Authorizer<H::Context>>,
+    method_normalizer: Option<MethodNormalizer>,
+    method_rewriter: Option<MethodRewriter>,
+    error_observer: Option<ErrorObserver>,
+    method_validator: Option<(MethodValidator, ErrorCode)>,
+    rate_limiter: Option<Box<dyn RateLimiter<H::Context> + Sync + Send>>,
+    result_transforms: HashMap<String, ResultTransform>,
+    response_audit: Option<ResponseAudit>,
+    id_propagator: Option<IdPropagator<H::Context>>,
+    error_registry: ErrorRegistry,
+    capabilities: Option<Json>,
+    initialized: AtomicBool,
+    history: History,
+}
+
+/**
+ * Method callable through HashMapWithMethods.
+ * */
+pub type Method = Box<dyn Fn(&JsonRpcRequest) -> Result<Json, ErrorJsonRpc> + 'static + Sync + Send>;
+
+/**
+ * Box closure as method accepted by `HashMapWithMethods`. Used by `register!` macro.
+ * */
+#[doc(hidden)]
+pub fn boxed_method<F>(f: F) -> Method
+    where F: Fn(&JsonRpcRequest) -> Result<Json, ErrorJsonRpc> + 'static + Sync + Send
+{
+    Box::new(f)
+}
+
+/**
+ * Build array result from iterator without collecting items first.
+ * `len_hint` is used to reserve capacity when iterator can't tell its length.
+ * */
+pub fn respond_iter<I, T>(len_hint: usize, iter: I) -> Result<Json, ErrorJsonRpc>
+    where I: Iterator<Item = T>,
+          T: ToJson
+{
+    let mut array = Vec::with_capacity(len_hint.max(iter.size_hint().0));
+    array.extend(iter.map(|item| item.to_json()));
+    Ok(Json::Array(array))
+}
+
+/**
+ * Outcome of method which has nothing to return, but still answers the call
+ * (see `HandlerResult::Ack` and `HashMapWithMethods::insert_outcome`).
+ * */
+pub fn ack() -> Result<HandlerResult, ErrorJsonRpc> {
+    Ok(HandlerResult::Ack)
+}
+
+/**
+ * Outcome of method which answers call in other way (see `HandlerResult::Suppress` and
+ * `HashMapWithMethods::insert_outcome`).
+ * */
+pub fn suppress() -> Result<HandlerResult, ErrorJsonRpc> {
+    Ok(HandlerResult::Suppress)
+}
 
+impl JsonRpcServer<HashMapWithMethods> {
     /**
-     * Get handler reference
+     * Create new default instance of JsonRpcServer.
      * */
-    pub fn get_handler(&self) -> &H {
-        &self.handler
+    pub fn new() -> JsonRpcServer<HashMapWithMethods> {
+        JsonRpcServer::new_handler(Default::default())
     }
+}
 
+impl<C: 'static> JsonRpcServer<BoxedHandler<C>> {
     /**
-     * Get mutable handler reference
+     * Create server with type erased handler.
      * */
-    pub fn get_handler_mut(&mut self) -> &mut H {
-        &mut self.handler
+    pub fn new_boxed(h: BoxedHandler<C>) -> JsonRpcServer<BoxedHandler<C>> {
+        JsonRpcServer::new_handler(h)
+    }
+}
+
+impl Default for JsonRpcServer<HashMapWithMethods> {
+    fn default() -> JsonRpcServer<HashMapWithMethods> {
+        JsonRpcServer::new()
+    }
+}
+
+impl From<ParserError> for InternalErrorCode {
+    fn from(_: ParserError) -> InternalErrorCode {
+        InternalErrorCode::WithoutId(ErrorCode::ParseError, None)
+    }
+}
+/**
+ * Decode request bytes. UTF-8 BOM is skipped, UTF-16 is recognized only by BOM.
+ * On failure returns reason reported to client.
+ * */
+fn decode_request(bytes: &[u8]) -> Result<Cow<'_, str>, &'static str> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        ::std::str::from_utf8(rest).map(Cow::Borrowed).map_err(|_| "invalid-utf8")
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        decode_utf16(rest, u16::from_le_bytes).map(Cow::Owned).ok_or("invalid-utf16")
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        decode_utf16(rest, u16::from_be_bytes).map(Cow::Owned).ok_or("invalid-utf16")
+    } else {
+        ::std::str::from_utf8(bytes).map(Cow::Borrowed).map_err(|_| "invalid-utf8")
+    }
+}
+
+/**
+ * Error data describing reason of failure.
+ * */
+fn reason_data(reason: &str) -> Json {
+    let mut data = BTreeMap::new();
+    data.insert("reason".to_owned(), reason.to_json());
+    Json::Object(data)
+}
+
+/**
+ * Parse error reported for request which is not valid text.
+ * */
+fn encoding_error(reason: &str) -> InternalErrorCode {
+    InternalErrorCode::WithoutId(ErrorCode::ParseError, Some(reason_data(reason)))
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units: Vec<u16> = bytes.chunks(2).map(|c| unit([c[0], c[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+impl <H: Handler> JsonRpcServer<H> where H::Context: Default {
+    /// Specialized implementation for context implementing default trait
+    pub fn handle_request(&self, req: &str) -> Option<String> {
+        self.handle_request_context(req, &Default::default())
+    }
+}
+impl <H: Handler> JsonRpcServer<H> {
+    /**
+     * Create instance of JsonRpcServer with custom handler
+     * */
+    pub fn new_handler(h: H) -> JsonRpcServer<H> {
+        JsonRpcServer {
+            handler: h,
+            options: Default::default(),
+            authorizer: None,
+            method_normalizer: None,
+            method_rewriter: None,
+            error_observer: None,
+            method_validator: None,
+            rate_limiter: None,
+            result_transforms: HashMap::new(),
+            response_audit: None,
+            id_propagator: None,
+            error_registry: ErrorRegistry::new(),
+            capabilities: None,
+            initialized: AtomicBool::new(false),
+            history: History::default(),
+        }
+    }
+
+    /**
+     * Enable strict mode. In strict mode request containing members other than
+     * `jsonrpc`, `method`, `params` and `id` is rejected as Invalid Request.
+     * */
+    pub fn set_strict(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    /**
+     * Limit length of accepted request. Longer requests are rejected as Invalid Request
+     * without parsing. None disables limit.
+     * */
+    pub fn set_max_request_bytes(&mut self, limit: Option<usize>) {
+        self.options.max_request_bytes = limit;
+    }
+
+    /**
+     * Limit length of serialized response. Longer response (whole array for batches) is replaced
+     * with Internal error carrying its length. None disables limit.
+     * */
+    pub fn set_max_response_bytes(&mut self, limit: Option<usize>) {
+        self.options.max_response_bytes = limit;
+    }
+
+    /**
+     * Limit number of requests in single batch. Bigger batches are rejected as Invalid Request.
+     * None disables limit.
+     * */
+    pub fn set_max_batch_size(&mut self, limit: Option<usize>) {
+        self.options.max_batch_size = limit;
+    }
+
+    /**
+     * Echo name of called method as `method` member of successful responses.
+     * This is non-standard extension intended for debugging, disabled by default.
+     * */
+    pub fn set_echo_method(&mut self, echo: bool) {
+        self.options.echo_method = echo;
+    }
+
+    /**
+     * Reject requests with boolean id as Invalid Request. Specification allows only string,
+     * number or null ids, but by default boolean ids are accepted and echoed back.
+     * */
+    pub fn set_reject_bool_id(&mut self, reject: bool) {
+        self.options.reject_bool_id = reject;
+    }
+
+    /**
+     * Wrap every successful result in object under given key, eg. `19` becomes
+     * `{"value": 19}` for `Some("value")`. None (default) leaves results intact.
+     * */
+    pub fn set_result_wrapper(&mut self, key: Option<&'static str>) {
+        self.options.result_wrapper = key;
+    }
+
+    /**
+     * Validate whole request and report all failures as array of messages in `data` of
+     * Invalid Request error. By default validation stops on first failure and no data is sent.
+     * */
+    pub fn set_collect_validation_errors(&mut self, collect: bool) {
+        self.options.collect_validation_errors = collect;
+    }
+
+    /**
+     * Accept request sent as JSON string containing actual request (as done by some
+     * misconfigured clients). Disabled by default.
+     * */
+    pub fn set_allow_double_encoded(&mut self, allow: bool) {
+        self.options.allow_double_encoded = allow;
+    }
+
+    /**
+     * Serialize responses with indentation and newlines (eg. for debugging tools).
+     * By default responses are compact.
+     * */
+    pub fn set_pretty(&mut self, pretty: bool) {
+        self.options.pretty = pretty;
+    }
+
+    /**
+     * Report time spent in handler as non-standard `_timingMs` member of successful responses.
+     * Meant for development only, as strict clients may reject unknown members. Disabled by default.
+     * */
+    pub fn set_include_timing(&mut self, include: bool) {
+        self.options.include_timing = include;
+    }
+
+    /**
+     * Send results of `ack()` without `result` member instead of `null` result.
+     * Such responses are not valid JSON-RPC 2.0, so enable only for clients expecting them.
+     * */
+    pub fn set_omit_ack_result(&mut self, omit: bool) {
+        self.options.omit_ack_result = omit;
+    }
+
+    /**
+     * Answer each notification in batch with `{"jsonrpc": "2.0", "result": null, "id": null}`
+     * acknowledgement. Notification which failed (invalid, unknown method, panicked handler) is
+     * answered with its error and null id instead. This is not allowed by specification, which
+     * requires notifications to be omitted (the default), so enable only for clients expecting it.
+     * */
+    pub fn set_ack_notifications(&mut self, ack: bool) {
+        self.options.ack_notifications = ack;
+    }
+
+    /**
+     * Validate, authorize and route requests, but answer them with stub result
+     * `{"_dryRun": true, "method": <method>}` instead of calling handler, eg. for contract
+     * testing of clients. Methods unknown to handler are still answered with Method not found.
+     * */
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.options.dry_run = dry_run;
+    }
+
+    /**
+     * Include `"jsonrpc": "2.0"` member in responses (the default). Some legacy clients
+     * choke on it, responses without it are not valid JSON-RPC 2.0 though.
+     * */
+    pub fn set_emit_version_field(&mut self, emit: bool) {
+        self.options.omit_version_field = !emit;
+    }
+
+    /**
+     * Log only 1 in `rate` invalid request and unknown method events (0 and 1 log all of them).
+     * Responses are not affected.
+     * */
+    pub fn set_log_sampling(&mut self, rate: usize) {
+        self.options.log_sampler.rate = rate;
+    }
+
+    /**
+     * Keep last `capacity` requests processed by `handle_request_context` with their responses,
+     * see `recent`. 0 disables recording (the default).
+     * */
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /**
+     * Last `n` recorded requests with their responses (None if nothing was sent back), oldest first.
+     * */
+    pub fn recent(&self, n: usize) -> Vec<Exchange> {
+        self.history.recent(n)
+    }
+
+    /**
+     * Choose reaction to `ServerError` codes outside of -32099..-32000 returned by handler.
+     * By default they are silently replaced with `InternalError`.
+     * */
+    pub fn set_invalid_code_policy(&mut self, policy: InvalidCodePolicy) {
+        self.options.invalid_code_policy = policy;
+    }
+
+    /**
+     * Choose how calls to unknown methods in batch are answered. With `Ignore` they are left
+     * out of batch response, so newer clients may probe older servers. Single requests are
+     * always answered with `MethodNotFound`.
+     * */
+    pub fn set_unknown_method_policy(&mut self, policy: UnknownMethodPolicy) {
+        self.options.unknown_method_policy = policy;
+    }
+
+    /**
+     * Attach deprecation notice of called method as non-standard `_deprecation` member of
+     * successful responses. Disabled by default.
+     * */
+    pub fn set_report_deprecation(&mut self, report: bool) {
+        self.options.report_deprecation = report;
+    }
+
+    /**
+     * Serialize responses with members in order `jsonrpc`, `result`/`error`, `id`, for clients
+     * which parse them positionally. By default members are sorted alphabetically.
+     * */
+    pub fn set_ordered_output(&mut self, ordered: bool) {
+        self.options.ordered_output = ordered;
+    }
+
+    /**
+     * Set callback invoked before each call to handler. By default all requests are allowed.
+     * */
+    pub fn set_authorizer(&mut self, authorizer: Authorizer<H::Context>) {
+        self.authorizer = Some(authorizer);
+    }
+
+    /**
+     * Set callback normalizing method names (eg. to make them case insensitive).
+     * Handler sees normalized method name.
+     * */
+    pub fn set_method_normalizer(&mut self, normalizer: MethodNormalizer) {
+        self.method_normalizer = Some(normalizer);
+    }
+
+    /**
+     * Set callback rewriting method names before dispatch, applied after normalizer.
+     * Handler, rate limiter and authorizer see rewritten name.
+     * */
+    pub fn set_method_rewriter(&mut self, rewriter: MethodRewriter) {
+        self.method_rewriter = Some(rewriter);
+    }
+
+    /**
+     * Set callback notified about every error response, eg. for monitoring.
+     * Errors of notifications are not reported, as no response is sent for them.
+     * */
+    pub fn set_error_observer(&mut self, observer: ErrorObserver) {
+        self.error_observer = Some(observer);
+    }
+
+    /**
+     * Set callback receiving every serialized response (single or batch) just before it is
+     * returned, eg. for audit log. It is not invoked when nothing is sent back.
+     * */
+    pub fn set_response_audit(&mut self, audit: ResponseAudit) {
+        self.response_audit = Some(audit);
+    }
+
+    /**
+     * Set application defined errors published by `error_catalog`.
+     * */
+    pub fn set_error_registry(&mut self, registry: ErrorRegistry) {
+        self.error_registry = registry;
+    }
+
+    /**
+     * Set callback rejecting method names not matching naming scheme. Rejected requests are
+     * answered with given error (eg. `InvalidRequest` or `MethodNotFound`) with offending
+     * method in data. By default all names are accepted.
+     * */
+    pub fn set_method_validator(&mut self, validator: MethodValidator, error: ErrorCode) {
+        self.method_validator = Some((validator, error));
+    }
+
+    /**
+     * Set limiter checked before authorization of each request. Calls exceeding limit are
+     * answered with `ServerError(-32029, "rate limited")`. By default calls are not limited.
+     * */
+    pub fn set_rate_limiter(&mut self, limiter: Box<dyn RateLimiter<H::Context> + Sync + Send>) {
+        self.rate_limiter = Some(limiter);
+    }
+
+    /**
+     * Set transform applied to successful results of given method (after normalization),
+     * before result wrapper. Replaces previous transform of that method.
+     * */
+    pub fn set_per_method_result_transform(&mut self, method: &str, transform: ResultTransform) {
+        self.result_transforms.insert(method.to_owned(), transform);
+    }
+
+    /**
+     * Notify error observer about error.
+     * */
+    fn observe_error(&self, error: &InternalErrorCode, method: Option<&str>) {
+        if let Some(ref observe) = self.error_observer {
+            match *error {
+                InternalErrorCode::WithId(ref code, Some(ref id), _) => observe(code.get_code(), method, Some(id)),
+                InternalErrorCode::WithId(_, None, _) => {}
+                InternalErrorCode::WithoutId(ref code, _) => observe(code.get_code(), method, None),
+            }
+        }
+    }
+
+    /**
+     * Validate request, returning its method, params and id.
+     * */
+    fn _validate<'a>(&self, req: &'a Json) -> Result<(&'a str, Option<&'a Json>, Option<&'a Json>), InternalErrorCode> {
+        validate_request(&self.options, req)
+    }
+
+    fn _handle_single(&self,
+                      req: &Json,
+                      custom: &H::Context)
+                      -> Result<JsonRpcResponse, InternalErrorCode> {
+        self._handle_streamed(req, custom, None)
+    }
+
+    /**
+     * Process single request, passing `sink` to handler if result may be streamed. Streamed
+     * result is answered with placeholder empty array, valid as long as nothing was pushed.
+     * */
+    fn _handle_streamed(&self,
+                        req: &Json,
+                        custom: &H::Context,
+                        sink: Option<&mut StreamingHandlerResult>)
+                        -> Result<JsonRpcResponse, InternalErrorCode> {
+
+        let (request_method, request_params, request_id) = self._validate(req)
+                                                                .inspect_err(|e| self.observe_error(e, None))?;
+
+        if let Some((ref validate, ref error)) = self.method_validator {
+            if !validate(request_method) {
+                if self.options.log_sampler.sample() {
+                    error!("Method name '{}' rejected by validator", request_method.escape_debug());
+                }
+                let mut data = BTreeMap::new();
+                data.insert("method".to_owned(), request_method.to_json());
+                let error = InternalErrorCode::WithId(error.clone(), request_id.cloned(), Some(Json::Object(data)));
+                self.observe_error(&error, Some(request_method));
+                return Err(error);
+            }
+        }
+
+        let mut request_method = match self.method_normalizer {
+            Some(ref normalize) => normalize(request_method),
+            None => Cow::Borrowed(request_method),
+        };
+
+        if let Some(ref rewrite) = self.method_rewriter {
+            let rewritten = match rewrite(&request_method) {
+                Cow::Borrowed(m) if m == request_method => None,
+                m => Some(m.into_owned()),
+            };
+            if let Some(rewritten) = rewritten {
+                request_method = Cow::Owned(rewritten);
+            }
+        }
+
+        // From now request is considered as VALID and code should use WithId
+        let request = JsonRpcRequest {
+            method: request_method,
+            params: request_params,
+            id: request_id,
+            raw: Some(req),
+        };
+
+        #[cfg(feature = "tracing")]
+        let _span = info_span!("jsonrpc.request", method = %request.method, id = ?request.id).entered();
+
+        if let Some(propagate) = self.id_propagator {
+            propagate(custom, request.id);
+        }
+
+        // Caller would never see result, so it is surely a bug on its side
+        if request.id.is_none() && self.handler.requires_response(&request.method) {
+            warn!("Method '{}' requires response, but was called as notification, ignoring it", request.method);
+            return Ok(JsonRpcResponse::suppressed());
+        }
+
+        let allowed = match self.rate_limiter {
+            Some(ref limiter) => limiter.check(&request.method, custom).map_err(|retry_after| {
+                warn!("Call of '{}' rate limited", request.method);
+                rate_limited(retry_after)
+            }),
+            None => Ok(()),
+        };
+
+        let authorized = allowed.and_then(|_| match self.authorizer {
+            Some(ref authorize) => authorize(&request, custom),
+            None => Ok(()),
+        });
+
+        let started = Instant::now();
+        let handled = authorized.and_then(|_| {
+            if let Some(outcome) = self.handshake(&request) {
+                outcome.map(HandlerResult::Value)
+            } else if self.options.dry_run {
+                self.dry_run_result(&request).map(HandlerResult::Value)
+            } else {
+                match sink {
+                    Some(sink) => self.handler.handle_streaming(&request, custom, sink),
+                    None => self.handler.handle_outcome(&request, custom),
+                }
+            }
+        });
+        let elapsed = started.elapsed();
+
+        handled.map(|outcome| {
+                if outcome == HandlerResult::Suppress {
+                    info!("Response to '{}' suppressed by method", request.method);
+                    return JsonRpcResponse::suppressed();
+                }
+                if outcome == HandlerResult::Streamed {
+                    // Result is written by caller, transforms can't see it
+                    return JsonRpcResponse::new_result(&request, Json::Array(Vec::new()));
+                }
+                let acknowledged = outcome == HandlerResult::Ack;
+                let s = outcome.into_json();
+                if self.handler.is_notification_only(&request.method) && s != Json::Null {
+                    // Bug in method, but notification contract is kept
+                    warn!("Notification-only method '{}' returned result {}, dropping it", request.method, s);
+                    return JsonRpcResponse::suppressed();
+                }
+                let s = if acknowledged {
+                    Json::Null
+                } else {
+                    self.wrap_result(match self.result_transforms.get(&*request.method) {
+                        Some(transform) => transform(s),
+                        None => s,
+                    })
+                };
+                let mut response = if self.options.echo_method {
+                    JsonRpcResponse::new_result_with_method(&request, s)
+                } else {
+                    JsonRpcResponse::new_result(&request, s)
+                };
+                if acknowledged && self.options.omit_ack_result && response.id.is_some() {
+                    response.result = None;
+                    response.result_omitted = true;
+                }
+                if self.options.include_timing {
+                    response.timing_ms = Some(elapsed.as_millis() as u64);
+                }
+                if self.options.report_deprecation {
+                    response.deprecation = self.handler.deprecation(&request.method).map(Deprecation::to_json);
+                }
+                response
+            })
+            .map_err(|e| {
+                if !e.error.is_valid() {
+                    self.report_invalid_code(&request, &e.error);
+                }
+                if e.get_code() == -32601 && self.options.log_sampler.sample() {
+                    error!("Requested method '{}' not found!", request.method);
+                }
+                let error = InternalErrorCode::WithId(e.error, request.id.cloned(), e.data);
+                self.observe_error(&error, Some(&request.method));
+                error
+            })
+    }
+
+    /**
+     * Outcome of request decided by `initialize` handshake. None if request should be passed
+     * to handler.
+     * */
+    fn handshake(&self, request: &JsonRpcRequest) -> Option<Result<Json, ErrorJsonRpc>> {
+        let capabilities = self.capabilities.as_ref()?;
+        if request.method == INITIALIZE_METHOD {
+            self.initialized.store(true, Ordering::SeqCst);
+            Some(Ok(capabilities.clone()))
+        } else if self.options.require_initialize && !self.is_initialized() {
+            warn!("Call of '{}' before initialization", request.method);
+            Some(Err(ErrorJsonRpc::new(ErrorCode::ServerError(-32002, "Server not initialized"))))
+        } else {
+            None
+        }
+    }
+
+    /**
+     * Stub result of request in dry run mode.
+     * */
+    fn dry_run_result(&self, request: &JsonRpcRequest) -> Result<Json, ErrorJsonRpc> {
+        if !self.handler.has_method(&request.method) {
+            return Err(ErrorJsonRpc::new(ErrorCode::MethodNotFound));
+        }
+        let mut stub = BTreeMap::new();
+        stub.insert("_dryRun".to_owned(), true.to_json());
+        stub.insert("method".to_owned(), request.method.to_json());
+        Ok(Json::Object(stub))
+    }
+
+    /**
+     * Apply policy for invalid error code returned by handler.
+     * */
+    fn report_invalid_code(&self, request: &JsonRpcRequest, error: &ErrorCode) {
+        match self.options.invalid_code_policy {
+            InvalidCodePolicy::Silent => {}
+            InvalidCodePolicy::Panic if cfg!(debug_assertions) => {
+                panic!("Method '{}' returned invalid error code {}", request.method, error.get_code());
+            }
+            InvalidCodePolicy::LogAndDowngrade | InvalidCodePolicy::Panic => {
+                warn!("Method '{}' returned invalid error code {}, sending Internal error instead",
+                      request.method,
+                      error.get_code());
+            }
+        }
+    }
+
+    /**
+     * Apply configured result wrapper.
+     * */
+    fn wrap_result(&self, result: Json) -> Json {
+        match self.options.result_wrapper {
+            Some(key) => {
+                let mut d = BTreeMap::new();
+                d.insert(key.to_owned(), result);
+                Json::Object(d)
+            }
+            None => result,
+        }
+    }
+
+    fn _handle_multiple(&self,
+                        array: &rustc_serialize::json::Array,
+                        custom: &H::Context)
+                        -> Result<Option<Json>, InternalErrorCode> {
+        self._check_batch(array.len())?;
+
+        #[cfg(feature = "tracing")]
+        let _span = info_span!("jsonrpc.batch", size = array.len()).entered();
+
+        let mut batch = ResponseBatch::with_capacity(array.len());
+        for request in array {
+            batch.push(self._handle_element(request, custom));
+        }
+
+        // All notifications nothing to respond
+        Ok(batch.finish())
+    }
+
+    /**
+     * Reject empty batch or batch exceeding configured size limit.
+     * */
+    fn _check_batch(&self, len: usize) -> Result<(), InternalErrorCode> {
+        if len == 0 {
+            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
+        }
+
+        if self.options.max_batch_size.is_some_and(|limit| len > limit) {
+            if self.options.log_sampler.sample() {
+                error!("Batch of {} requests exceeds limit", len);
+            }
+            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
+        }
+        Ok(())
+    }
+
+    /**
+     * Process single element of batch. None if element should not be answered.
+     * */
+    fn _handle_element(&self, request: &Json, custom: &H::Context) -> Option<Json> {
+        info!("Processing {}", request);
+        // Panic in one request must not abort whole batch
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self._handle_single(request, custom)))
+            .unwrap_or_else(|_| {
+                error!("Panic while processing {}", request);
+                let error = InternalErrorCode::WithId(ErrorCode::InternalError, request.get("id").cloned(), None);
+                self.observe_error(&error, request.get("method").and_then(JsonValue::as_str));
+                Err(error)
+            });
+        let acked = self.options.ack_notifications && request.is_object() && request.get("id").is_none();
+        if acked && result.as_ref().is_ok_and(|response| response.id.is_none()) {
+            let ack = JsonRpcResponse {
+                result: Some(Json::Null),
+                id: Some(Json::Null),
+                ..JsonRpcResponse::suppressed()
+            };
+            return Some(ack.to_json());
+        }
+        // Convert any error to Json
+        let response = result.unwrap_or_else(|e| match e {
+            // Failed notification is answered with error instead of acknowledgement
+            InternalErrorCode::WithId(err, None, data) if acked => {
+                InternalErrorCode::WithId(err, Some(Json::Null), data).into_response()
+            }
+            e => e.into_response(),
+        });
+        let ignored = self.options.unknown_method_policy == UnknownMethodPolicy::Ignore &&
+                      response.error.as_ref().is_some_and(|e| e.get_code() == -32601);
+        // Skip notifications in response
+        response.id.as_ref().filter(|_| !ignored).map(|_| response.to_json())
+    }
+
+    /**
+     * Reject request exceeding configured size limit.
+     * */
+    fn _check_size(&self, len: usize) -> Result<(), InternalErrorCode> {
+        if self.options.max_request_bytes.is_some_and(|limit| len > limit) {
+            if self.options.log_sampler.sample() {
+                error!("Request of {} bytes exceeds limit", len);
+            }
+            return Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None));
+        }
+        Ok(())
+    }
+
+    fn _handle_request(&self,
+                       request: &str,
+                       custom: &H::Context) -> Result<Option<Json>, InternalErrorCode> {
+        // Checked before parser is even created
+        self._check_size(request.len())?;
+
+        // Parser rejects trailing characters by itself
+        let request_json = Json::from_str(request)?;
+        self._handle_parsed(&request_json, custom)
+    }
+
+    /**
+     * Dispatch already parsed request.
+     * */
+    fn _handle_parsed(&self, request_json: &Json, custom: &H::Context) -> Result<Option<Json>, InternalErrorCode> {
+        // for now only plain object support
+        match *request_json {
+            // Errors of single request are already observed, keep them away from generic path
+            Json::Object(_) => {
+                let response = self._handle_single(request_json, custom)
+                                   .unwrap_or_else(InternalErrorCode::into_response);
+                Ok(Some(response.to_json()))
+            }
+            Json::Array(ref a) => self._handle_multiple(a, custom),
+            Json::String(ref inner) if self.options.allow_double_encoded => {
+                warn!("Request is double encoded");
+                // Unwrap only one level
+                match Json::from_str(inner)? {
+                    Json::String(_) => Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None)),
+                    inner => self._handle_parsed(&inner, custom),
+                }
+            }
+            _ => Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None)),
+        }
+    }
+
+    /**
+     * Convert outcome of request processing to response. None if nothing should be sent back.
+     * */
+    fn _response_json(&self, result: Result<Option<Json>, InternalErrorCode>) -> Option<Json> {
+        self._response_envelope(result).map(|response| self._versioned(response))
+    }
+
+    /**
+     * Apply `omit_version_field` option to response (or batch of them).
+     * */
+    fn _versioned(&self, response: Json) -> Json {
+        if self.options.omit_version_field {
+            without_version(response)
+        } else {
+            response
+        }
+    }
+
+    fn _response_envelope(&self, result: Result<Option<Json>, InternalErrorCode>) -> Option<Json> {
+        match result {
+            Ok(Some(resp)) if resp != Json::Null => Some(resp),
+            // Notification (but got some data?), no returned response anyway
+            Ok(Some(ref some)) => {
+                warn!("Co to jest?: {:?}", some);
+                None
+            }
+            Ok(_) => None,
+            Err(err) => {
+                self.observe_error(&err, None);
+                let response = err.into_response().to_json();
+                if response == Json::Null {
+                    println!("Empty");
+                    None
+                } else {
+                    Some(response)
+                }
+            }
+        }
+    }
+
+    /**
+     * Serialize outcome of request processing. None if nothing should be sent back.
+     * */
+    fn _response_string(&self, result: Result<Option<Json>, InternalErrorCode>) -> Option<String> {
+        self._response_json(result).map(|response| {
+            let serialized = self.serialize(&response);
+            let serialized = match self.options.max_response_bytes {
+                Some(limit) if serialized.len() > limit => self.serialize(&self.oversized(&response, serialized.len())),
+                _ => serialized,
+            };
+            if let Some(ref audit) = self.response_audit {
+                audit(&serialized);
+            }
+            serialized
+        })
+    }
+
+    /**
+     * Error response replacing response exceeding size limit. Batch is replaced as a whole.
+     * */
+    fn oversized(&self, response: &Json, bytes: usize) -> Json {
+        error!("Response of {} bytes exceeds limit", bytes);
+        let mut data = BTreeMap::new();
+        data.insert("reason".to_owned(), "response too large".to_json());
+        data.insert("bytes".to_owned(), bytes.to_json());
+        let id = response.find("id").cloned().unwrap_or(Json::Null);
+        let error = InternalErrorCode::WithId(ErrorCode::InternalError, Some(id), Some(Json::Object(data)));
+        self.observe_error(&error, None);
+        self._versioned(error.into_response().to_json())
+    }
+
+    /**
+     * Serialize response, compact unless pretty output is enabled.
+     * Ordered output takes precedence over pretty one.
+     * */
+    fn serialize(&self, response: &Json) -> String {
+        if self.options.ordered_output {
+            ordered_string(response)
+        } else if self.options.pretty {
+            response.pretty().to_string()
+        } else {
+            response.to_string()
+        }
+    }
+
+    /**
+     * Process request using given context. Request must contain exactly one JSON value,
+     * any non-whitespace content after it is reported as Parse error.
+     * */
+    pub fn handle_request_context(&self, request: &str, custom: &H::Context) -> Option<String> {
+        let result = self._handle_request(request, custom);
+        let response = self._response_string(result);
+        self.history.record(request, response.as_ref());
+        response
+    }
+
+    /**
+     * Same as `handle_request_context`, but response is returned as UTF-8 bytes ready to be
+     * written to socket. Buffer of serialized response is reused, nothing is copied.
+     * */
+    pub fn handle_request_vec(&self, request: &str, custom: &H::Context) -> Option<Vec<u8>> {
+        self.handle_request_context(request, custom).map(String::into_bytes)
+    }
+
+    /**
+     * Process batch already decoded into separate values. Returns batch response
+     * or None if there is nothing to send back.
+     * */
+    pub fn handle_value_batch(&self, requests: Vec<Json>, custom: &H::Context) -> Option<Json> {
+        let result = self._handle_multiple(&requests, custom);
+        self._response_json(result)
+    }
+
+    /**
+     * Process request given as raw bytes. Accepts UTF-8 (optionally prefixed with BOM)
+     * and UTF-16 prefixed with BOM. Undecodable input is answered with Parse error
+     * with `reason` in data set to `invalid-utf8` or `invalid-utf16`.
+     * */
+    pub fn handle_request_bytes(&self, request: &[u8], custom: &H::Context) -> Option<String> {
+        // Size of raw bytes is checked, so oversized request is never decoded
+        if let Err(err) = self._check_size(request.len()) {
+            return self._response_string(Err(err));
+        }
+        match decode_request(request) {
+            Ok(request) => self.handle_request_context(&request, custom),
+            Err(reason) => {
+                if self.options.log_sampler.sample() {
+                    error!("Request is not valid text: {}", reason);
+                }
+                self._response_string(Err(encoding_error(reason)))
+            }
+        }
+    }
+
+    /**
+     * Get handler reference
+     * */
+    pub fn get_handler(&self) -> &H {
+        &self.handler
+    }
+
+    /**
+     * Get mutable handler reference
+     * */
+    pub fn get_handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+}
+
+/**
+ * Builder for configured JsonRpcServer.
+ * */
+pub struct JsonRpcServerBuilder<H: Handler + 'static> {
+    server: JsonRpcServer<H>,
+}
+
+impl<H: Handler> JsonRpcServerBuilder<H> {
+    /**
+     * Start building server with given handler and default configuration.
+     * */
+    pub fn new(h: H) -> JsonRpcServerBuilder<H> {
+        JsonRpcServerBuilder { server: JsonRpcServer::new_handler(h) }
+    }
+
+    /**
+     * See `JsonRpcServer::set_strict`.
+     * */
+    pub fn strict(mut self, strict: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_strict(strict);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_max_request_bytes`.
+     * */
+    pub fn max_request_bytes(mut self, limit: usize) -> JsonRpcServerBuilder<H> {
+        self.server.set_max_request_bytes(Some(limit));
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_max_response_bytes`.
+     * */
+    pub fn max_response_bytes(mut self, limit: usize) -> JsonRpcServerBuilder<H> {
+        self.server.set_max_response_bytes(Some(limit));
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_max_batch_size`.
+     * */
+    pub fn max_batch_size(mut self, limit: usize) -> JsonRpcServerBuilder<H> {
+        self.server.set_max_batch_size(Some(limit));
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_echo_method`.
+     * */
+    pub fn echo_method(mut self, echo: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_echo_method(echo);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_reject_bool_id`.
+     * */
+    pub fn reject_bool_id(mut self, reject: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_reject_bool_id(reject);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_result_wrapper`.
+     * */
+    pub fn result_wrapper(mut self, key: &'static str) -> JsonRpcServerBuilder<H> {
+        self.server.set_result_wrapper(Some(key));
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_collect_validation_errors`.
+     * */
+    pub fn collect_validation_errors(mut self, collect: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_collect_validation_errors(collect);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_allow_double_encoded`.
+     * */
+    pub fn allow_double_encoded(mut self, allow: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_allow_double_encoded(allow);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_pretty`.
+     * */
+    pub fn pretty(mut self, pretty: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_pretty(pretty);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_include_timing`.
+     * */
+    pub fn include_timing(mut self, include: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_include_timing(include);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_omit_ack_result`.
+     * */
+    pub fn omit_ack_result(mut self, omit: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_omit_ack_result(omit);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_ack_notifications`.
+     * */
+    pub fn ack_notifications(mut self, ack: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_ack_notifications(ack);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_dry_run`.
+     * */
+    pub fn dry_run(mut self, dry_run: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_dry_run(dry_run);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_emit_version_field`.
+     * */
+    pub fn emit_version_field(mut self, emit: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_emit_version_field(emit);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_log_sampling`.
+     * */
+    pub fn log_sampling(mut self, rate: usize) -> JsonRpcServerBuilder<H> {
+        self.server.set_log_sampling(rate);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_history_capacity`.
+     * */
+    pub fn history_capacity(mut self, capacity: usize) -> JsonRpcServerBuilder<H> {
+        self.server.set_history_capacity(capacity);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_invalid_code_policy`.
+     * */
+    pub fn invalid_code_policy(mut self, policy: InvalidCodePolicy) -> JsonRpcServerBuilder<H> {
+        self.server.set_invalid_code_policy(policy);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_unknown_method_policy`.
+     * */
+    pub fn unknown_method_policy(mut self, policy: UnknownMethodPolicy) -> JsonRpcServerBuilder<H> {
+        self.server.set_unknown_method_policy(policy);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_report_deprecation`.
+     * */
+    pub fn report_deprecation(mut self, report: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_report_deprecation(report);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_ordered_output`.
+     * */
+    pub fn ordered_output(mut self, ordered: bool) -> JsonRpcServerBuilder<H> {
+        self.server.set_ordered_output(ordered);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_method_normalizer`.
+     * */
+    pub fn method_normalizer(mut self, normalizer: MethodNormalizer) -> JsonRpcServerBuilder<H> {
+        self.server.set_method_normalizer(normalizer);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_method_rewriter`.
+     * */
+    pub fn method_rewriter(mut self, rewriter: MethodRewriter) -> JsonRpcServerBuilder<H> {
+        self.server.set_method_rewriter(rewriter);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_error_observer`.
+     * */
+    pub fn error_observer(mut self, observer: ErrorObserver) -> JsonRpcServerBuilder<H> {
+        self.server.set_error_observer(observer);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_response_audit`.
+     * */
+    pub fn response_audit(mut self, audit: ResponseAudit) -> JsonRpcServerBuilder<H> {
+        self.server.set_response_audit(audit);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_error_registry`.
+     * */
+    pub fn error_registry(mut self, registry: ErrorRegistry) -> JsonRpcServerBuilder<H> {
+        self.server.set_error_registry(registry);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_method_validator`.
+     * */
+    pub fn method_validator(mut self, validator: MethodValidator, error: ErrorCode) -> JsonRpcServerBuilder<H> {
+        self.server.set_method_validator(validator, error);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_rate_limiter`.
+     * */
+    pub fn rate_limiter(mut self, limiter: Box<dyn RateLimiter<H::Context> + Sync + Send>) -> JsonRpcServerBuilder<H> {
+        self.server.set_rate_limiter(limiter);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_per_method_result_transform`.
+     * */
+    pub fn per_method_result_transform(mut self, method: &str, transform: ResultTransform) -> JsonRpcServerBuilder<H> {
+        self.server.set_per_method_result_transform(method, transform);
+        self
+    }
+
+    /**
+     * See `JsonRpcServer::set_authorizer`.
+     * */
+    pub fn authorizer(mut self, authorizer: Authorizer<H::Context>) -> JsonRpcServerBuilder<H> {
+        self.server.set_authorizer(authorizer);
+        self
+    }
+
+    /**
+     * Finish building server.
+     * */
+    pub fn build(self) -> JsonRpcServer<H> {
+        self.server
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+
+    /**
+     * Handler answering each call with number of calls so far. Method `fail` fails instead.
+     * Shared by tests of wrapping handlers.
+     * */
+    #[derive(Default)]
+    pub struct Counter {
+        pub calls: AtomicUsize,
+    }
+
+    impl Handler for Counter {
+        type Context = ();
+        fn handle(&self, req: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if req.method == "fail" {
+                return Err(ErrorJsonRpc::new(ErrorCode::InternalError));
+            }
+            Ok(calls.to_json())
+        }
+    }
+
+    /**
+     * Server with method `subtract` always returning 19, shared by tests of transports.
+     * */
+    pub fn subtract_server() -> JsonRpcServer<HashMapWithMethods> {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        JsonRpcServer::new_handler(handler)
+    }
+
+    // tests from JSON-RPC RFC
+    #[test]
+    fn test_positional() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \
+                       \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_named() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": \
+                       {\"subtrahend\": 23, \"minuend\": 42}, \"id\": 3}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 3}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_empty_params() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("simple_method".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"simple_method\", \"params\": null, \
+                       \"id\": 3}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 3}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"simple_method\", \"params\": null, \
+                       \"id\": 3}";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_notification() {
+        // --> {"jsonrpc": "2.0", "method": "update", "params": [1,2,3,4,5]}
+        // --> {"jsonrpc": "2.0", "method": "foobar"}
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("update".to_owned(), Box::new(|_| Ok(Json::Null)));
+        handler.insert("foobar".to_owned(), Box::new(|_| Ok(Json::Null)));
+        let server = JsonRpcServer::new_handler(handler);
+        let response = server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"update\", \
+                                              \"params\": [1,2,3,4,5]}");
+        assert_eq!(None, response);
+        assert_eq!(None,
+                   server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"foobar\"}"));
+    }
+
+    #[test]
+    fn test_non_existing_method() {
+        // --> {"jsonrpc": "2.0", "method": "foobar", "id": "1"}
+        // <-- {"jsonrpc": "2.0", "error": {"code": -32601, "message": "Method not
+        // found"}, "id": "1"}
+        let server = JsonRpcServer::new();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\",
+            \"id\": \"1\"}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\",
+            \"error\": \
+                                                {\"code\": -32601, \"message\": \"Method not \
+                                                found\"},
+            \"id\": \"1\"}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+    #[test]
+    fn test_call_invalid_json() {
+        // --> {"jsonrpc": "2.0", "method": "foobar, "params": "bar", "baz]
+        // <-- {"jsonrpc": "2.0", "error": {"code": -32700, "message": "Parse error"},
+        // "id": null}
+        let server = JsonRpcServer::new();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar, \"params\": \"bar\", \"baz]";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\"}, \"id\": \
+                                                null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_invalid_request() {
+        // --> {"jsonrpc": "2.0", "method": 1, "params": "bar"}
+        // <-- {"jsonrpc": "2.0", "error": {"code": -32600, "message": "Invalid
+        // Request"}, "id": null}
+        let server = JsonRpcServer::new();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": 1, \"params\": \"bar\"}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_batch_invalid_json() {
+        let request = "[
+            {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": \
+                       [1,2,4], \"id\": \"1\"},
+            {\"jsonrpc\": \"2.0\", \"method\"
+        \
+                       ]";
+        let server = JsonRpcServer::new();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\"}, \"id\": \
+                                                null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_with_empty_array() {
+        let request = "[]";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let server = JsonRpcServer::new();
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_with_invalid_batch_not_empty() {
+        let request = "[1]";
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}]");
+        let server = JsonRpcServer::new();
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_with_invalid_batch() {
+        let request = "[1,2,3]";
+        let expected_response = Json::from_str("[
+            {\"jsonrpc\": \"2.0\", \"error\": \
+                                                {\"code\": -32600, \"message\": \"Invalid \
+                                                Request\"}, \"id\": null},
+            \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null},
+            {\"jsonrpc\": \
+                                                \"2.0\", \"error\": {\"code\": -32600, \
+                                                \"message\": \"Invalid Request\"}, \"id\": null}
+            \
+                                                ]");
+        let server = JsonRpcServer::new();
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_batch_nested() {
+        // Batch element must be request object, nested batches are never unwrapped
+        let request = "[[1,2], {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}, [], \
+                       [{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 2}]]";
+        let invalid = "{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \"message\": \"Invalid Request\"}, \
+                       \"id\": null}";
+        let expected_response = Json::from_str(&format!("[{0}, {{\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": 1}}, \
+                                                         {0}, {0}]",
+                                                        invalid));
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("sum".to_owned(), Box::new(|_| Ok(7.to_json())));
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        // Nested arrays are not notifications, so they are not acknowledged nor ignored
+        server.set_ack_notifications(true);
+        server.set_unknown_method_policy(UnknownMethodPolicy::Ignore);
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+
+    #[test]
+    fn test_call_batch() {
+        let request = "[
+        {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": [1,2,4], \
+                       \"id\": \"1\"},
+        {\"jsonrpc\": \"2.0\", \"method\": \
+                       \"notify_hello\", \"params\": [7]},
+        {\"jsonrpc\": \"2.0\", \
+                       \"method\": \"subtract\", \"params\": [42,23], \"id\": \"2\"},
+        \
+                       {\"foo\": \"boo\"},
+        {\"jsonrpc\": \"2.0\", \"method\": \
+                       \"foo.get\", \"params\": {\"name\": \"myself\"}, \"id\": \"5\"},
+        \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"get_data\", \"id\": \"9\"}
+        ]";
+
+        let expected_response = Json::from_str("[
+        {\"jsonrpc\": \"2.0\", \"result\": 7, \
+                                                \"id\": \"1\"},
+        {\"jsonrpc\": \"2.0\", \
+                                                \"result\": 19, \"id\": \"2\"},
+        \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null},
+        {\"jsonrpc\": \"2.0\", \
+                                                \"error\": {\"code\": -32601, \"message\": \
+                                                \"Method not found\"}, \"id\": \"5\"},
+        \
+                                                {\"jsonrpc\": \"2.0\", \"result\": [\"hello\", \
+                                                5], \"id\": \"9\"}
+        ]");
+
+        let mut server = JsonRpcServer::new();
+        {
+            let handler = server.get_handler_mut();
+            handler.insert("sum".to_owned(), Box::new(|_| Ok(7.to_json())));
+            handler.insert("notify_hello".to_owned(), Box::new(|_| Ok(Json::Null)));
+            handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+            handler.insert("get_data".to_owned(),
+                           Box::new(|_| Ok(vec!["hello".to_json(), 5.to_json()].to_json())));
+        }
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_batch_all_notifications() {
+        let request = "[
+        {\"jsonrpc\": \"2.0\", \"method\": \"notify_sum\", \"params\": \
+                       [1,2,4]},
+        {\"jsonrpc\": \"2.0\", \"method\": \"notify_hello\", \
+                       \"params\": [7]}
+        ]";
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("notify_sum".to_owned(), Box::new(|_| Ok(Json::Null)));
+        handler.insert("notify_hello".to_owned(), Box::new(|_| Ok(Json::Null)));
+        let server = JsonRpcServer::new_handler(handler);
+        let response = server.handle_request(request);
+        assert_eq!(None, response);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServerBuilder::new(handler)
+                         .strict(true)
+                         .max_request_bytes(100)
+                         .max_batch_size(1)
+                         .build();
+        let invalid_request = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                              -32600, \"message\": \"Invalid Request\"}, \
+                                              \"id\": null}");
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // strict
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"foo\": 1, \"id\": 1}";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(invalid_request, response);
+
+        // max_request_bytes
+        let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": \
+                               [\"{}\"], \"id\": 1}}",
+                              "x".repeat(100));
+        let response = Json::from_str(&server.handle_request(&request).unwrap());
+        assert_eq!(invalid_request, response);
+
+        // max_batch_size
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1},
+                        {\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 2}]";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(invalid_request, response);
+    }
+
+    #[test]
+    fn test_authorizer() {
+        struct Echo;
+        impl Handler for Echo {
+            type Context = &'static str;
+            fn handle(&self, req: &JsonRpcRequest, _: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+                Ok(req.method.to_json())
+            }
+        }
+        let server = JsonRpcServerBuilder::new(Echo)
+                         .authorizer(Box::new(|req, token| {
+                             if req.method == "shutdown" && *token != "admin" {
+                                 Err(ErrorJsonRpc::new(ErrorCode::ServerError(-32001, "Unauthorized")))
+                             } else {
+                                 Ok(())
+                             }
+                         }))
+                         .build();
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"status\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"status\", \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request_context(request, &"guest").unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"shutdown\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32001, \"message\": \"Unauthorized\"}, \"id\": 2}");
+        let response = Json::from_str(&server.handle_request_context(request, &"guest").unwrap());
+        assert_eq!(expected_response, response);
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"shutdown\", \
+                                                \"id\": 2}");
+        let response = Json::from_str(&server.handle_request_context(request, &"admin").unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_empty_method() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("".to_owned(), Box::new(|_| Ok(1.to_json())));
+        handler.insert("   ".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"\", \"id\": 1}";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"   \", \"id\": 1}";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_error_code_from_code() {
+        match ErrorCode::from_code(-32601) {
+            ErrorCode::MethodNotFound => {}
+            other => panic!("Unexpected error code {:?}", other),
+        }
+        match ErrorCode::from_code(-32050) {
+            ErrorCode::ServerError(-32050, "Server error") => {}
+            other => panic!("Unexpected error code {:?}", other),
+        }
+        for code in &[-32700, -32600, -32601, -32602, -32603, -32000] {
+            assert_eq!(*code, ErrorCode::from_code(*code).get_code());
+        }
+    }
+
+    #[test]
+    fn test_response_consistency() {
+        let request = JsonRpcRequest {
+            method: Cow::Borrowed("foo"),
+            params: None,
+            id: Some(&Json::Null),
+            raw: None,
+        };
+        assert!(JsonRpcResponse::new_result(&request, 1.to_json()).is_consistent());
+        assert!(JsonRpcResponse::new_error(ErrorCode::InternalError, None, Some(Json::Null))
+                    .is_consistent());
+
+        let notification = JsonRpcRequest {
+            method: Cow::Borrowed("foo"),
+            params: None,
+            id: None,
+            raw: None,
+        };
+        assert!(JsonRpcResponse::new_result(&notification, 1.to_json()).is_consistent());
+        assert!(JsonRpcResponse::new_error(ErrorCode::InternalError, None, None).is_consistent());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Malformed response")]
+    fn test_malformed_response() {
+        let response = JsonRpcResponse {
+            result: Some(1.to_json()),
+            error: Some(ErrorJsonRpc::new(ErrorCode::InternalError)),
+            id: Some(1.to_json()),
+            method: None,
+            timing_ms: None,
+            deprecation: None,
+            result_omitted: false,
+        };
+        response.to_json();
+    }
+
+    #[test]
+    fn test_result_does_not_override_envelope() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("tricky".to_owned(),
+                       Box::new(|_| Ok(Json::from_str("{\"id\": 999, \"jsonrpc\": \"9.9\"}").unwrap())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"tricky\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": {\"id\": 999, \
+                                                \"jsonrpc\": \"9.9\"}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_request_bytes() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \
+                       \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+
+        let response = Json::from_str(&server.handle_request_bytes(request.as_bytes(), &()).unwrap());
+        assert_eq!(expected_response, response);
+
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend_from_slice(request.as_bytes());
+        let response = Json::from_str(&server.handle_request_bytes(&bytes, &()).unwrap());
+        assert_eq!(expected_response, response);
+
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(request.encode_utf16().flat_map(|u| u.to_le_bytes().to_vec()));
+        let response = Json::from_str(&server.handle_request_bytes(&bytes, &()).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_request_vec() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+
+        let response = server.handle_request_vec(request, &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(std::str::from_utf8(&response).unwrap()));
+        assert_eq!(None, server.handle_request_vec("{\"jsonrpc\": \"2.0\", \"method\": \"subtract\"}", &()));
+    }
+
+    #[test]
+    fn test_request_invalid_utf8() {
+        let server = JsonRpcServer::new();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\", \"data\": \
+                                                {\"reason\": \"invalid-utf8\"}}, \"id\": null}");
+        let request = b"{\"jsonrpc\": \"2.0\", \"method\": \"\xff\xfe\xfd\", \"id\": 1}";
+        let response = Json::from_str(&server.handle_request_bytes(request, &()).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Odd number of bytes can't be UTF-16
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\", \"data\": \
+                                                {\"reason\": \"invalid-utf16\"}}, \"id\": null}");
+        let response = Json::from_str(&server.handle_request_bytes(b"\xff\xfe{", &()).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_echo_method() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let mut server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let notification = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\"}";
+        let not_found = "{\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"id\": 2}";
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_echo_method(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1, \
+                                                \"method\": \"subtract\"}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+        assert_eq!(None, server.handle_request(notification));
+
+        // Only successful responses are extended
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32601, \"message\": \"Method not found\"}, \
+                                                \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(not_found).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_typed_params() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("repeat".to_owned(), Box::new(|req| {
+            let text = req.param_at_as_str(0)?;
+            let count = req.param_at_as_i64(1)?;
+            Ok(text.repeat(count as usize).to_json())
+        }));
+        handler.insert("scale".to_owned(), Box::new(|req| {
+            let value = req.param_named_as_f64("value")?;
+            let negate = req.param_named_as_bool("negate")?;
+            Ok((if negate { -value } else { value } * 2.0).to_json())
+        }));
+        let server = JsonRpcServer::new_handler(handler);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"repeat\", \"params\": [\"ab\", 2], \
+                       \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"abab\", \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"repeat\", \"params\": [2, \"ab\"], \
+                       \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32602, \"message\": \"Invalid params\", \"data\": \
+                                                \"parameter 0 must be string\"}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"scale\", \"params\": {\"value\": 1.5, \
+                       \"negate\": \"yes\"}, \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32602, \"message\": \"Invalid params\", \"data\": \
+                                                \"parameter 'negate' must be boolean\"}, \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"scale\", \"params\": {\"negate\": \
+                       true}, \"id\": 3}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32602, \"message\": \"Invalid params\", \"data\": \
+                                                \"missing parameter 'value'\"}, \"id\": 3}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_default_server() {
+        let mut server: JsonRpcServer<HashMapWithMethods> = Default::default();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_call_batch_with_panic() {
+        let request = "[
+        {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": \"1\"},
+        {\"jsonrpc\": \"2.0\", \"method\": \"crash\", \"id\": \"2\"},
+        {\"jsonrpc\": \"2.0\", \"method\": \"crash\"},
+        {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": \"3\"}
+        ]";
+        let expected_response = Json::from_str("[
+        {\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": \"1\"},
+        {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32603, \"message\": \"Internal error\"}, \
+                                                \"id\": \"2\"},
+        {\"jsonrpc\": \"2.0\", \"result\": 7, \"id\": \"3\"}
+        ]");
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("sum".to_owned(), Box::new(|_| Ok(7.to_json())));
+        handler.insert("crash".to_owned(), Box::new(|_| panic!("crash")));
+        let server = JsonRpcServer::new_handler(handler);
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("x".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\"}, \"id\": \
+                                                null}");
+        let request = "{\"jsonrpc\":\"2.0\",\"method\":\"x\",\"id\":1} garbage";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\":\"2.0\",\"method\":\"x\",\"id\":1}{}";
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Trailing whitespace is fine
+        let request = "{\"jsonrpc\":\"2.0\",\"method\":\"x\",\"id\":1} \r\n\t";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_method_normalizer() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("SUBTRACT".to_owned(), Box::new(|req| {
+            assert_eq!("SUBTRACT", req.method);
+            Ok(19.to_json())
+        }));
+        let server = JsonRpcServerBuilder::new(handler)
+                         .method_normalizer(Box::new(|m| Cow::Owned(m.to_uppercase())))
+                         .build();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"Subtract\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_method_rewriter() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("foo".to_owned(), Box::new(|req| {
+            assert_eq!("foo", req.method);
+            Ok(19.to_json())
+        }));
+        let server = JsonRpcServerBuilder::new(handler)
+                         .method_rewriter(Box::new(|m| Cow::Borrowed(m.strip_prefix("v1.").unwrap_or(m))))
+                         .build();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": \"v1.foo\"}");
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"v1.foo\", \"id\": \"v1.foo\"}";
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"id\": \"v1.foo\"}";
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+
+    #[test]
+    fn test_bool_id() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let mut server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": true}";
+
+        // Lenient by default, id is echoed back
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": true}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_reject_bool_id(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_result_wrapper() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let server = JsonRpcServerBuilder::new(handler).result_wrapper("value").build();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": {\"value\": 19}, \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Errors are not wrapped
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32601, \"message\": \"Method not found\"}, \
+                                                \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_collect_validation_errors() {
+        let mut server = JsonRpcServer::new();
+        let request = "{\"jsonrpc\": \"1.0\", \"method\": 1, \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_collect_validation_errors(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\", \"data\": \
+                                                [\"'jsonrpc' must be \\\"2.0\\\"\", \
+                                                \"'method' must be a non-empty string\"]}, \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_error_observer() {
+        use std::sync::{Arc, Mutex};
+
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let sink = observed.clone();
+        let mut server = JsonRpcServer::new();
+        server.set_error_observer(Box::new(move |code, method, id| {
+            sink.lock().unwrap().push((code, method.map(str::to_owned), id.cloned()));
+        }));
+        server.get_handler_mut().insert("ok".to_owned(), Box::new(|_| Ok(Json::Null)));
+
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"ok\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"missing\", \"id\": 2}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"missing\"}, \
+                       {\"foo\": \"boo\"}]";
+        server.handle_request(request).unwrap();
+        let id = Json::from_str("2").unwrap();
+        assert_eq!(vec![(-32601, Some("missing".to_owned()), Some(id)), (-32600, None, None)],
+                   *observed.lock().unwrap());
+
+        observed.lock().unwrap().clear();
+        server.handle_request("{\"jsonrpc\": \"2.0\", \"method\"").unwrap();
+        assert_eq!(vec![(-32700, None, None)], *observed.lock().unwrap());
+    }
+
+    #[test]
+    fn test_double_encoded() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let request = "\"{\\\"jsonrpc\\\": \\\"2.0\\\", \\\"method\\\": \\\"subtract\\\", \\\"id\\\": 1}\"";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_allow_double_encoded(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Only one level is unwrapped
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}".to_json().to_string();
+        let request = Json::String(request).to_string();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(&request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_app_error() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("withdraw".to_owned(), Box::new(|_| {
+            Err(ErrorJsonRpc::new(ErrorCode::AppError(1001, "Insufficient funds".to_owned())))
+        }));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"withdraw\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                1001, \"message\": \"Insufficient funds\"}, \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_respond_iter() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("squares".to_owned(), Box::new(|_| {
+            respond_iter(4, (1..).map(|x| x * x).take_while(|&x| x < 20))
+        }));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"squares\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": [1, 4, 9, 16], \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        assert_eq!(Json::Array(vec![]), respond_iter(10, Vec::<i32>::new().into_iter()).unwrap());
+    }
+
+    #[test]
+    fn test_method_validator() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("foo\nbar".to_owned(), Box::new(|_| Ok(Json::Null)));
+        server.set_method_validator(Box::new(|m| m.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')),
+                                    ErrorCode::InvalidRequest);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foo\\nbar\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\", \"data\": \
+                                                {\"method\": \"foo\\nbar\"}}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.get_handler_mut().insert("foo.bar".to_owned(), Box::new(|_| Ok(Json::Null)));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foo.bar\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": null, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_pretty() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let single = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let batch = "[{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}, {\"foo\": 1}]";
+        let invalid = "[]";
+        for request in &[single, batch, invalid] {
+            assert!(!server.handle_request(request).unwrap().contains('\n'));
+        }
+
+        server.set_pretty(true);
+        for request in &[single, batch, invalid] {
+            let response = server.handle_request(request).unwrap();
+            assert!(response.contains('\n'));
+            assert!(Json::from_str(&response).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_include_timing() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}";
+        let not_found = "{\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"id\": 2}";
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        assert!(response.find("_timingMs").is_none());
+
+        server.set_include_timing(true);
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        assert!(response.find("_timingMs").is_some_and(Json::is_u64));
+        let response = Json::from_str(&server.handle_request(not_found).unwrap()).unwrap();
+        assert!(response.find("_timingMs").is_none());
+    }
+
+    #[test]
+    fn test_ack() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert_outcome("ping".to_owned(), |_| ack());
+        server.set_result_wrapper(Some("data"));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": null, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_omit_ack_result(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Notifications are still not answered
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"ping\"}";
+        assert_eq!(None, server.handle_request(request));
+    }
+
+    #[test]
+    fn test_ack_not_forged_by_client() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut()
+              .insert("echo".to_owned(), Box::new(|req| Ok(req.param_at(0).cloned().unwrap_or(Json::Null))));
+        server.set_omit_ack_result(true);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [\"\\u0000ack\"], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"\\u0000ack\", \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+
+    #[test]
+    fn test_error_code_into() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("into".to_owned(), Box::new(|_| Err(ErrorCode::InvalidParams.into())));
+        server.get_handler_mut().insert("fail".to_owned(), Box::new(|_| fail(ErrorCode::InvalidParams)));
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32602, \"message\": \"Invalid params\"}, \
+                                                \"id\": 1}");
+        for method in &["into", "fail"] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"{}\", \"id\": 1}}", method);
+            let response = Json::from_str(&server.handle_request(&request).unwrap());
+            assert_eq!(expected_response, response);
+        }
+    }
+
+    fn invalid_code_server(policy: InvalidCodePolicy) -> JsonRpcServer<HashMapWithMethods> {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("bad".to_owned(), Box::new(|_| fail(ErrorCode::ServerError(1, "Bad"))));
+        server.set_invalid_code_policy(policy);
+        server
+    }
+
+    #[test]
+    fn test_invalid_code_policy() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"bad\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32603, \"message\": \"Internal error\"}, \
+                                                \"id\": 1}");
+        for policy in &[InvalidCodePolicy::Silent, InvalidCodePolicy::LogAndDowngrade] {
+            let response = Json::from_str(&invalid_code_server(*policy).handle_request(request).unwrap());
+            assert_eq!(expected_response, response);
+        }
+    }
+
+    #[cfg(all(feature = "logging", not(feature = "tracing")))]
+    #[test]
+    fn test_invalid_code_logged() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"bad\", \"id\": 1}";
+        let reported = || captured_logs().iter().filter(|m| m.contains("'bad' returned invalid error code 1")).count();
+        // Install logger before first event
+        reported();
+        invalid_code_server(InvalidCodePolicy::Silent).handle_request(request);
+        assert_eq!(0, reported());
+        invalid_code_server(InvalidCodePolicy::LogAndDowngrade).handle_request(request);
+        assert_eq!(1, reported());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "invalid error code 1")]
+    fn test_invalid_code_policy_panic() {
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"bad\", \"id\": 1}";
+        invalid_code_server(InvalidCodePolicy::Panic).handle_request(request);
+    }
+
+    #[test]
+    fn test_handle_value_batch() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let requests = vec![Json::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}").unwrap(),
+                            Json::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"id\": 2}").unwrap()];
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                                \"message\": \"Method not found\"}, \"id\": 2}]");
+        // Round trip through text, as handler results are not parsed numbers
+        let response = server.handle_value_batch(requests, &()).map(|r| Json::from_str(&r.to_string()));
+        assert_eq!(Some(expected_response), response);
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        assert_eq!(expected_response.ok(), server.handle_value_batch(vec![], &()));
+
+        let notification = Json::from_str("{\"jsonrpc\": \"2.0\", \"method\": \"subtract\"}").unwrap();
+        assert_eq!(None, server.handle_value_batch(vec![notification], &()));
+    }
+
+    #[test]
+    fn test_oversized_request_not_parsed() {
+        let mut server = JsonRpcServer::new();
+        server.set_max_request_bytes(Some(1024));
+        // Would be Parse error if parser ever saw it
+        let request = "{".repeat(1025);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(&request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Not even decoded
+        let mut request = request.into_bytes();
+        request[0] = 0xff;
+        let response = Json::from_str(&server.handle_request_bytes(&request, &()).unwrap());
+        assert_eq!(expected_response, response);
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\"}, \"id\": null}");
+        let response = Json::from_str(&server.handle_request(&"{".repeat(1024)).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_suppress() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert_outcome("queue".to_owned(), |_| suppress());
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"queue\", \"id\": 1}";
+        assert_eq!(None, server.handle_request(request));
+
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"queue\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 2}]";
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 2}]");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        // Client can't make echoing method suppress its response
+        server.get_handler_mut()
+              .insert("echo".to_owned(), Box::new(|req| Ok(req.param_at(0).cloned().unwrap_or(Json::Null))));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"echo\", \"params\": [\"\\u0000suppress\"], \"id\": 3}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"\\u0000suppress\", \"id\": 3}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+
+    #[test]
+    fn test_raw_method() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert_raw("traced".to_owned(), |raw| Ok(raw.find("trace").cloned().unwrap_or(Json::Null)));
+        server.set_strict(true);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"traced\", \"id\": 1, \"trace\": \"abc\"}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_strict(false);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"abc\", \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_params_not_structured() {
+        let server = JsonRpcServer::new();
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\", \"data\": \
+                                                {\"reason\": \"params must be array or object\"}}, \
+                                                \"id\": null}");
+        for params in &["true", "false", "42", "\"foo\""] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"params\": {}, \"id\": 1}}",
+                                  params);
+            let response = Json::from_str(&server.handle_request(&request).unwrap());
+            assert_eq!(expected_response, response, "{}", params);
+        }
+    }
+
+    #[test]
+    fn test_ordered_output() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        server.set_ordered_output(true);
+        server.set_echo_method(true);
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"foo\", \"id\": \"a\\\"b\"}]";
+        assert_eq!("[{\"jsonrpc\":\"2.0\",\"result\":19,\"id\":1,\"method\":\"subtract\"},\
+                    {\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32601,\"message\":\"Method not found\"},\
+                    \"id\":\"a\\\"b\"}]",
+                   server.handle_request(request).unwrap());
+
+        let request = JsonRpcRequest {
+            method: Cow::Borrowed("foo"),
+            params: None,
+            id: Some(&Json::Null),
+            raw: None,
+        };
+        assert_eq!("{\"jsonrpc\":\"2.0\",\"result\":true,\"id\":null}",
+                   JsonRpcResponse::new_result(&request, true.to_json()).to_ordered_string());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rustc_serialize::json::{Json, ToJson};
+    #[test]
+    fn test_sub_errors() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("bulk".to_owned(), Box::new(|_| {
+            Err(ErrorJsonRpc::with_sub_errors(ErrorCode::ServerError(-32010, "Partial failure"),
+                                              vec![(1, "item 1 not found".to_owned()),
+                                                   (2, "item 3 locked".to_owned())]))
+        }));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"bulk\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32010, \
+                                                \"message\": \"Partial failure\", \"data\": [\
+                                                {\"code\": 1, \"message\": \"item 1 not found\"}, \
+                                                {\"code\": 2, \"message\": \"item 3 locked\"}]}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
 
-    // tests from JSON-RPC RFC
     #[test]
-    fn test_positional() {
+    fn test_empty_error_message() {
         let mut handler = HashMapWithMethods::new();
-        handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
+        handler.insert("server".to_owned(), Box::new(|_| fail(ErrorCode::ServerError(-32010, ""))));
+        handler.insert("app".to_owned(), Box::new(|_| fail(ErrorCode::AppError(-32602, String::new()))));
         let server = JsonRpcServer::new_handler(handler);
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \
-                       \"id\": 1}";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+        for &(method, code, message) in &[("server", -32010, "Server error"), ("app", -32602, "Invalid params")] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"{}\", \"id\": 1}}", method);
+            let expected_response = Json::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"error\": \
+                                                             {{\"code\": {}, \"message\": \"{}\"}}, \
+                                                             \"id\": 1}}",
+                                                            code,
+                                                            message));
+            let response = Json::from_str(&server.handle_request(&request).unwrap());
+            assert_eq!(expected_response, response);
+        }
     }
 
     #[test]
-    fn test_named() {
+    fn test_unknown_method_policy() {
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"newer\", \"id\": 2}]";
         let mut handler = HashMapWithMethods::new();
         handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
-        let server = JsonRpcServer::new_handler(handler);
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": \
-                       {\"subtrahend\": 23, \"minuend\": 42}, \"id\": 3}";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 3}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+        let mut server = JsonRpcServer::new_handler(handler);
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                                \"message\": \"Method not found\"}, \"id\": 2}]");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        server.set_unknown_method_policy(UnknownMethodPolicy::Ignore);
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": 19, \"id\": 1}]");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"newer\", \"id\": 2}]";
+        assert_eq!(None, server.handle_request(request));
     }
 
     #[test]
-    fn test_empty_params() {
+    fn test_boxed_handlers() {
+        struct Echo;
+        impl Handler for Echo {
+            type Context = ();
+            fn handle(&self, req: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+                Ok(req.method.to_json())
+            }
+        }
         let mut handler = HashMapWithMethods::new();
-        handler.insert("simple_method".to_owned(), Box::new(|_| Ok(1.to_json())));
-        let server = JsonRpcServer::new_handler(handler);
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"simple_method\", \"params\": null, \
-                       \"id\": 3}";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 3}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+        handler.insert("name".to_owned(), Box::new(|_| Ok("methods".to_json())));
+        let router = NamespaceRouter::new().mount("app", handler);
+        let servers: Vec<JsonRpcServer<BoxedHandler<()>>> = vec![JsonRpcServer::new_boxed(Box::new(router)),
+                                                                 JsonRpcServer::new_boxed(Box::new(Echo))];
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"app.name\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"methods\", \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&servers[0].handle_request(request).unwrap()));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"status\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"status\", \"id\": 2}");
+        assert_eq!(expected_response, Json::from_str(&servers[1].handle_request(request).unwrap()));
+    }
 
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"simple_method\", \"params\": null, \
-                       \"id\": 3}";
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    #[test]
+    fn test_per_method_result_transform() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("secret".to_owned(), Box::new(|_| Ok("abc".to_json())));
+        handler.insert("plain".to_owned(), Box::new(|_| Ok("abc".to_json())));
+        let server = JsonRpcServerBuilder::new(handler)
+                         .per_method_result_transform("secret", Box::new(|result| {
+                             let mut d = BTreeMap::new();
+                             d.insert("encoded".to_owned(), result);
+                             Json::Object(d)
+                         }))
+                         .build();
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"secret\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": {\"encoded\": \"abc\"}, \
+                                                \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"plain\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"abc\", \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
     }
 
     #[test]
-    fn test_notification() {
-        // --> {"jsonrpc": "2.0", "method": "update", "params": [1,2,3,4,5]}
-        // --> {"jsonrpc": "2.0", "method": "foobar"}
+    fn test_max_response_bytes() {
         let mut handler = HashMapWithMethods::new();
-        handler.insert("update".to_owned(), Box::new(|_| Ok(Json::Null)));
-        handler.insert("foobar".to_owned(), Box::new(|_| Ok(Json::Null)));
-        let server = JsonRpcServer::new_handler(handler);
-        let response = server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"update\", \
-                                              \"params\": [1,2,3,4,5]}");
-        assert_eq!(None, response);
-        assert_eq!(None,
-                   server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"foobar\"}"));
+        handler.insert("big".to_owned(), Box::new(|_| Ok("x".repeat(1000).to_json())));
+        handler.insert("small".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServerBuilder::new(handler).max_response_bytes(200).build();
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"small\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"big\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32603, \
+                                                \"message\": \"Internal error\", \"data\": {\"reason\": \
+                                                \"response too large\", \"bytes\": 1036}}, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        // Batch is limited as a whole, even if each response fits
+        let request = format!("[{}]", ["{\"jsonrpc\": \"2.0\", \"method\": \"small\", \"id\": 1}"; 10].join(","));
+        let response = Json::from_str(&server.handle_request(&request).unwrap()).unwrap();
+        assert_eq!(Some(-32603), response.find_path(&["error", "code"]).and_then(Json::as_i64));
+        assert_eq!(Some(&Json::Null), response.find("id"));
     }
 
     #[test]
-    fn test_non_existing_method() {
-        // --> {"jsonrpc": "2.0", "method": "foobar", "id": "1"}
-        // <-- {"jsonrpc": "2.0", "error": {"code": -32601, "message": "Method not
-        // found"}, "id": "1"}
-        let server = JsonRpcServer::new();
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar\",
-            \"id\": \"1\"}";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\",
-            \"error\": \
-                                                {\"code\": -32601, \"message\": \"Method not \
-                                                found\"},
-            \"id\": \"1\"}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_positional_or_named() {
+        let params = Json::from_str("{\"__positional\": [1, 2], \"extra\": true}").unwrap();
+        let mut request = JsonRpcRequest {
+            method: Cow::Borrowed("sum"),
+            params: Some(&params),
+            id: None,
+            raw: None,
+        };
+        assert_eq!(Params::Positional(params["__positional"].as_array().unwrap()),
+                   request.positional_or_named());
+
+        let named = Json::from_str("{\"a\": 1, \"__positional\": 2}").unwrap();
+        request.params = Some(&named);
+        assert_eq!(Params::Named(named.as_object().unwrap()), request.positional_or_named());
+
+        let positional = Json::from_str("[1, 2]").unwrap();
+        request.params = Some(&positional);
+        assert_eq!(Params::Positional(positional.as_array().unwrap()), request.positional_or_named());
+
+        request.params = None;
+        assert_eq!(Params::Empty, request.positional_or_named());
     }
+
     #[test]
-    fn test_call_invalid_json() {
-        // --> {"jsonrpc": "2.0", "method": "foobar, "params": "bar", "baz]
-        // <-- {"jsonrpc": "2.0", "error": {"code": -32700, "message": "Parse error"},
-        // "id": null}
-        let server = JsonRpcServer::new();
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"foobar, \"params\": \"bar\", \"baz]";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32700, \"message\": \"Parse error\"}, \"id\": \
-                                                null}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_response_audit() {
+        use std::sync::{Arc, Mutex};
+
+        let audited = Arc::new(Mutex::new(Vec::new()));
+        let sink = audited.clone();
+        let mut server = JsonRpcServer::new();
+        server.set_response_audit(Box::new(move |response| sink.lock().unwrap().push(response.to_owned())));
+        server.get_handler_mut().insert("ok".to_owned(), Box::new(|_| Ok(Json::Null)));
+
+        let requests = ["{\"jsonrpc\": \"2.0\", \"method\": \"ok\", \"id\": 1}",
+                        "{\"jsonrpc\": \"2.0\", \"method\": \"ok\"}",
+                        "[{\"jsonrpc\": \"2.0\", \"method\": \"ok\"}, {\"jsonrpc\": \"2.0\", \"method\": \"ok\", \"id\": 2}]",
+                        "[{\"jsonrpc\": \"2.0\", \"method\": \"ok\"}]"];
+        let responses: Vec<String> = requests.iter().filter_map(|r| server.handle_request(r)).collect();
+        assert_eq!(2, responses.len());
+        assert_eq!(responses, *audited.lock().unwrap());
     }
 
     #[test]
-    fn test_call_invalid_request() {
-        // --> {"jsonrpc": "2.0", "method": 1, "params": "bar"}
-        // <-- {"jsonrpc": "2.0", "error": {"code": -32600, "message": "Invalid
-        // Request"}, "id": null}
-        let server = JsonRpcServer::new();
-        let request = "{\"jsonrpc\": \"2.0\", \"method\": 1, \"params\": \"bar\"}";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32600, \"message\": \"Invalid Request\"}, \
-                                                \"id\": null}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_id_as_i64() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("id".to_owned(), Box::new(|req| Ok(req.id_as_i64().to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        for &(id, result) in &[("123", "123"), ("\"123\"", "123"), ("\"-5\"", "-5"), ("\"abc\"", "null"),
+                               ("1.5", "null")] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"id\", \"id\": {}}}", id);
+            let expected_response = Json::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"result\": {}, \"id\": {}}}",
+                                                            result,
+                                                            id));
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(&request).unwrap()), "{}", id);
+        }
     }
 
     #[test]
-    fn test_call_batch_invalid_json() {
-        let request = "[
-            {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": \
-                       [1,2,4], \"id\": \"1\"},
-            {\"jsonrpc\": \"2.0\", \"method\"
-        \
-                       ]";
-        let server = JsonRpcServer::new();
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32700, \"message\": \"Parse error\"}, \"id\": \
-                                                null}");
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_ack_notifications() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("log".to_owned(), Box::new(|_| Ok(true.to_json())));
+        let mut server = JsonRpcServer::new_handler(handler);
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"log\"}, {\"jsonrpc\": \"2.0\", \"method\": \"missing\"}]";
+        assert_eq!(None, server.handle_request(request));
+
+        server.set_ack_notifications(true);
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": null, \"id\": null}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                                \"message\": \"Method not found\"}, \"id\": null}]");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        // Invalid and panicking elements are not acknowledged
+        server.get_handler_mut().insert("panic".to_owned(), Box::new(|_| panic!("boom")));
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"log\"}, {\"foo\": \"boo\"}, \
+                       {\"jsonrpc\": \"1.0\", \"method\": \"log\"}, {\"jsonrpc\": \"2.0\", \"method\": \"panic\"}]";
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        let codes: Vec<_> = response.as_array()
+                                    .unwrap()
+                                    .iter()
+                                    .map(|response| response.find_path(&["error", "code"]).and_then(Json::as_i64))
+                                    .collect();
+        assert_eq!(vec![None, Some(-32600), Some(-32600), Some(-32603)], codes);
+
+        // Single notification is still not answered
+        assert_eq!(None, server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"log\"}"));
     }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn test_call_with_empty_array() {
-        let request = "[]";
-        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32600, \"message\": \"Invalid Request\"}, \
-                                                \"id\": null}");
-        let server = JsonRpcServer::new();
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_tracing_spans() {
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        struct Fields<'a>(&'a mut Vec<String>);
+        impl<'a> Visit for Fields<'a> {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0.push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        struct Spans(Arc<Mutex<Vec<String>>>);
+        impl Subscriber for Spans {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut fields = Vec::new();
+                span.record(&mut Fields(&mut fields));
+                let mut spans = self.0.lock().unwrap();
+                spans.push(format!("{} {}", span.metadata().name(), fields.join(" ")));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("ping".to_owned(), Box::new(|_| Ok(true.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        tracing::subscriber::with_default(Spans(spans.clone()), || {
+            server.handle_request("[{\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"id\": 1}]");
+        });
+        assert_eq!(vec!["jsonrpc.batch size=1", "jsonrpc.request method=ping id=Some(U64(1))"],
+                   *spans.lock().unwrap());
     }
 
     #[test]
-    fn test_call_with_invalid_batch_not_empty() {
-        let request = "[1]";
-        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32600, \"message\": \"Invalid Request\"}, \
-                                                \"id\": null}]");
-        let server = JsonRpcServer::new();
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_dry_run() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("transfer".to_owned(), Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(true.to_json())
+        }));
+        let server = JsonRpcServerBuilder::new(handler).dry_run(true).build();
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"transfer\", \"params\": [100], \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"missing\", \"id\": 2}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"transfer\", \"params\": 5, \"id\": 3}]";
+        let expected_response = Json::from_str("[{\"jsonrpc\": \"2.0\", \"result\": {\"_dryRun\": true, \
+                                                \"method\": \"transfer\"}, \"id\": 1}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                                \"message\": \"Method not found\"}, \"id\": 2}, \
+                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32600, \
+                                                \"message\": \"Invalid Request\", \"data\": {\"reason\": \
+                                                \"params must be array or object\"}}, \"id\": null}]");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        assert_eq!(0, calls.load(Ordering::SeqCst));
     }
 
     #[test]
-    fn test_call_with_invalid_batch() {
-        let request = "[1,2,3]";
-        let expected_response = Json::from_str("[
-            {\"jsonrpc\": \"2.0\", \"error\": \
-                                                {\"code\": -32600, \"message\": \"Invalid \
-                                                Request\"}, \"id\": null},
-            \
-                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32600, \"message\": \"Invalid Request\"}, \
-                                                \"id\": null},
-            {\"jsonrpc\": \
-                                                \"2.0\", \"error\": {\"code\": -32600, \
-                                                \"message\": \"Invalid Request\"}, \"id\": null}
-            \
-                                                ]");
-        let server = JsonRpcServer::new();
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+    fn test_emit_version_field() {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("one".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let mut server = JsonRpcServer::new_handler(handler);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"one\", \"id\": 1}";
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        assert_eq!(Some(&"2.0".to_json()), response.find("jsonrpc"));
+
+        server.set_emit_version_field(false);
+        let expected_response = Json::from_str("{\"result\": 1, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        let expected_response = Json::from_str("[{\"result\": 1, \"id\": 1}, \
+                                                {\"error\": {\"code\": -32601, \"message\": \"Method not found\"}, \"id\": 2}]");
+        let batch = "[{\"jsonrpc\": \"2.0\", \"method\": \"one\", \"id\": 1}, \
+                     {\"jsonrpc\": \"2.0\", \"method\": \"two\", \"id\": 2}]";
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(batch).unwrap()));
+
+        // Error replacing oversized response is stripped as well
+        server.get_handler_mut().insert("big".to_owned(), Box::new(|_| Ok("x".repeat(1000).to_json())));
+        server.set_max_response_bytes(Some(200));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"big\", \"id\": 1}";
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        assert_eq!(Some(-32603), response.find_path(&["error", "code"]).and_then(Json::as_i64));
+        assert_eq!(None, response.find("jsonrpc"));
+    }
+
+    /**
+     * Messages logged so far by current thread. Records are tagged with thread name, so events
+     * of tests running in parallel are skipped.
+     * */
+    #[cfg(all(feature = "logging", not(feature = "tracing")))]
+    fn captured_logs() -> Vec<String> {
+        use log::{self, Log, LogLevelFilter, LogMetadata, LogRecord};
+        use std::sync::Mutex;
+        use std::thread;
+
+        static CAPTURED: Mutex<Vec<(Option<String>, String)>> = Mutex::new(Vec::new());
+        struct Capture;
+        impl Log for Capture {
+            fn enabled(&self, _: &LogMetadata) -> bool {
+                true
+            }
+            fn log(&self, record: &LogRecord) {
+                let thread = thread::current().name().map(str::to_owned);
+                CAPTURED.lock().unwrap().push((thread, record.args().to_string()));
+            }
+        }
+        let _ = log::set_logger(|max_level| {
+            max_level.set(LogLevelFilter::Info);
+            Box::new(Capture)
+        });
+        let thread = thread::current().name().map(str::to_owned);
+        CAPTURED.lock().unwrap().iter().filter(|&(t, _)| *t == thread).map(|(_, m)| m.clone()).collect()
     }
 
+    #[cfg(all(feature = "logging", not(feature = "tracing")))]
     #[test]
-    fn test_call_batch() {
-        let request = "[
-        {\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": [1,2,4], \
-                       \"id\": \"1\"},
-        {\"jsonrpc\": \"2.0\", \"method\": \
-                       \"notify_hello\", \"params\": [7]},
-        {\"jsonrpc\": \"2.0\", \
-                       \"method\": \"subtract\", \"params\": [42,23], \"id\": \"2\"},
-        \
-                       {\"foo\": \"boo\"},
-        {\"jsonrpc\": \"2.0\", \"method\": \
-                       \"foo.get\", \"params\": {\"name\": \"myself\"}, \"id\": \"5\"},
-        \
-                       {\"jsonrpc\": \"2.0\", \"method\": \"get_data\", \"id\": \"9\"}
-        ]";
+    fn test_log_sampling() {
+        let not_found = || captured_logs().iter().filter(|m| m.contains("not found")).count();
+        // Install logger before first event
+        not_found();
 
-        let expected_response = Json::from_str("[
-        {\"jsonrpc\": \"2.0\", \"result\": 7, \
-                                                \"id\": \"1\"},
-        {\"jsonrpc\": \"2.0\", \
-                                                \"result\": 19, \"id\": \"2\"},
-        \
-                                                {\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
-                                                -32600, \"message\": \"Invalid Request\"}, \
-                                                \"id\": null},
-        {\"jsonrpc\": \"2.0\", \
-                                                \"error\": {\"code\": -32601, \"message\": \
-                                                \"Method not found\"}, \"id\": \"5\"},
-        \
-                                                {\"jsonrpc\": \"2.0\", \"result\": [\"hello\", \
-                                                5], \"id\": \"9\"}
-        ]");
+        let mut server = JsonRpcServer::new_handler(HashMapWithMethods::new());
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"missing\", \"id\": 1}";
+        for _ in 0..10 {
+            server.handle_request(request);
+        }
+        assert_eq!(10, not_found());
 
-        let mut server = JsonRpcServer::new();
-        {
-            let mut handler = server.get_handler_mut();
-            handler.insert("sum".to_owned(), Box::new(|_| Ok(7.to_json())));
-            handler.insert("notify_hello".to_owned(), Box::new(|_| Ok(Json::Null)));
-            handler.insert("subtract".to_owned(), Box::new(|_| Ok(19.to_json())));
-            handler.insert("get_data".to_owned(),
-                           Box::new(|_| Ok(vec!["hello".to_json(), 5.to_json()].to_json())));
+        server.set_log_sampling(5);
+        for _ in 0..10 {
+            assert!(server.handle_request(request).unwrap().contains("-32601"));
         }
-        let response = Json::from_str(&server.handle_request(request).unwrap());
-        assert_eq!(expected_response, response);
+        assert_eq!(12, not_found());
     }
 
     #[test]
-    fn test_call_batch_all_notifications() {
-        let request = "[
-        {\"jsonrpc\": \"2.0\", \"method\": \"notify_sum\", \"params\": \
-                       [1,2,4]},
-        {\"jsonrpc\": \"2.0\", \"method\": \"notify_hello\", \
-                       \"params\": [7]}
-        ]";
+    fn test_notification_only_result() {
         let mut handler = HashMapWithMethods::new();
-        handler.insert("notify_sum".to_owned(), Box::new(|_| Ok(Json::Null)));
-        handler.insert("notify_hello".to_owned(), Box::new(|_| Ok(Json::Null)));
+        handler.insert_notification("log".to_owned(), Box::new(|_| Ok("logged".to_json())));
         let server = JsonRpcServer::new_handler(handler);
-        let response = server.handle_request(request);
-        assert_eq!(None, response);
+        #[cfg(all(feature = "logging", not(feature = "tracing")))]
+        captured_logs();
+
+        assert_eq!(None, server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"log\"}"));
+        #[cfg(all(feature = "logging", not(feature = "tracing")))]
+        assert!(captured_logs().iter().any(|m| m.contains("Notification-only method 'log' returned")));
+
+        // Result is never sent back, even when method is called with id
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"log\", \"id\": 1}";
+        assert_eq!(None, server.handle_request(request));
+    }
+
+    #[test]
+    fn test_requires_response() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let mut handler = HashMapWithMethods::new();
+        handler.insert_requiring_response("balance".to_owned(), Box::new(|_| {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(10.to_json())
+        }));
+        let server = JsonRpcServer::new_handler(handler);
+        #[cfg(all(feature = "logging", not(feature = "tracing")))]
+        captured_logs();
+
+        assert_eq!(None, server.handle_request("{\"jsonrpc\": \"2.0\", \"method\": \"balance\"}"));
+        assert_eq!(0, CALLS.load(Ordering::SeqCst));
+        #[cfg(all(feature = "logging", not(feature = "tracing")))]
+        assert!(captured_logs().iter().any(|m| m.contains("Method 'balance' requires response")));
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 10, \"id\": 1}");
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"balance\", \"id\": 1}";
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        assert_eq!(1, CALLS.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_decoder_error() {
+        struct Point {
+            x: i32,
+        }
+        impl Decodable for Point {
+            fn decode<D: ::rustc_serialize::Decoder>(d: &mut D) -> Result<Point, D::Error> {
+                d.read_struct("Point", 1, |d| Ok(Point { x: d.read_struct_field("x", 0, Decodable::decode)? }))
+            }
+        }
+
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("x".to_owned(), Box::new(|req| {
+            let params = req.params.cloned().unwrap_or(Json::Null);
+            let point = Point::decode(&mut Decoder::new(params))?;
+            Ok(point.x.to_json())
+        }));
+        let server = JsonRpcServer::new_handler(handler);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"x\", \"params\": {\"x\": 4}, \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 4, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"x\", \"params\": {\"x\": true}, \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32602, \
+                                                \"message\": \"Invalid params\", \"data\": \
+                                                \"expected Number, found true\"}, \"id\": 2}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"x\", \"params\": {\"y\": 4}, \"id\": 3}";
+        let response = server.handle_request(request).unwrap();
+        assert!(response.contains("\"data\":\"missing field 'x'\""), "{}", response);
     }
 }
@@ -0,0 +1,200 @@
+//! Macros simplifying method registration.
+
+/**
+ * Insert methods into existing `HashMapWithMethods`.
+ *
+ * ```
+ * # #[macro_use] extern crate jsonrpc;
+ * # extern crate rustc_serialize;
+ * # use rustc_serialize::json::ToJson;
+ * # fn main() {
+ * let mut methods = jsonrpc::HashMapWithMethods::new();
+ * register!(methods, "ping" => |_| Ok("pong".to_json()));
+ * # }
+ * ```
+ * */
+#[macro_export]
+macro_rules! register {
+    ($map:expr, $($name:expr => $method:expr),* $(,)*) => {{
+        $( $map.insert(::std::string::String::from($name), $crate::boxed_method($method)); )*
+    }};
+}
+
+/**
+ * Build `HashMapWithMethods` from list of methods.
+ *
+ * ```
+ * # #[macro_use] extern crate jsonrpc;
+ * # extern crate rustc_serialize;
+ * # use rustc_serialize::json::ToJson;
+ * # fn main() {
+ * let server = jsonrpc::JsonRpcServer::new_handler(methods! {
+ *     "ping" => |_| Ok("pong".to_json()),
+ *     "echo" => |req| Ok(req.params.cloned().unwrap_or(rustc_serialize::json::Json::Null)),
+ * });
+ * # }
+ * ```
+ * */
+#[macro_export]
+macro_rules! methods {
+    ($($name:expr => $method:expr),* $(,)*) => {{
+        let mut map = $crate::HashMapWithMethods::new();
+        $crate::register!(map, $($name => $method),*);
+        map
+    }};
+}
+
+/**
+ * Generate enum of methods and `Handler` implementation dispatching each of them to method
+ * of given type. Misspelled or missing method is reported at compile time.
+ *
+ * ```
+ * # #[macro_use] extern crate jsonrpc;
+ * # extern crate rustc_serialize;
+ * # use rustc_serialize::json::{Json, ToJson};
+ * # use jsonrpc::{ErrorJsonRpc, JsonRpcRequest};
+ * struct Greeter;
+ *
+ * impl Greeter {
+ *     fn hello(&self, _: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+ *         Ok("hello".to_json())
+ *     }
+ * }
+ *
+ * method_enum! {
+ *     pub enum GreeterMethod for Greeter, Context = () {
+ *         Hello = "hello" => hello,
+ *     }
+ * }
+ * # fn main() {
+ * assert_eq!(Some(GreeterMethod::Hello), GreeterMethod::from_name("hello"));
+ * let server = jsonrpc::JsonRpcServer::new_handler(Greeter);
+ * # }
+ * ```
+ * */
+#[macro_export]
+macro_rules! method_enum {
+    ($vis:vis enum $name:ident for $handler:ty, Context = $context:ty {
+        $($variant:ident = $method:expr => $func:ident),* $(,)*
+    }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            /**
+             * Find method by name.
+             * */
+            pub fn from_name(name: &str) -> ::std::option::Option<$name> {
+                match name {
+                    $($method => ::std::option::Option::Some($name::$variant),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            /**
+             * Get name of method.
+             * */
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $($name::$variant => $method),*
+                }
+            }
+        }
+
+        impl $crate::Handler for $handler {
+            type Context = $context;
+            fn handle(&self,
+                      req: &$crate::JsonRpcRequest,
+                      custom: &$context)
+                      -> ::std::result::Result<$crate::Json, $crate::ErrorJsonRpc> {
+                match $name::from_name(&req.method) {
+                    $(::std::option::Option::Some($name::$variant) => self.$func(req, custom),)*
+                    ::std::option::Option::None => {
+                        ::std::result::Result::Err($crate::ErrorJsonRpc::new($crate::ErrorCode::MethodNotFound))
+                    }
+                }
+            }
+
+            fn has_method(&self, method: &str) -> bool {
+                $name::from_name(method).is_some()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::{Json, ToJson};
+    use {ErrorJsonRpc, JsonRpcRequest, JsonRpcServer};
+
+    #[test]
+    fn test_methods_macro() {
+        let offset = 10;
+        let server = JsonRpcServer::new_handler(methods! {
+            "sum" => move |req| {
+                let params = req.params.and_then(|p| p.as_array()).map_or(0, |a| {
+                    a.iter().filter_map(|v| v.as_i64()).sum()
+                });
+                Ok((params + offset).to_json())
+            },
+            "name" => |req| Ok(req.method.to_json()),
+        });
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"params\": [1, 2], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 13, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"name\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"name\", \
+                                                \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    struct Calculator {
+        offset: i64,
+    }
+
+    impl Calculator {
+        fn add(&self, req: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+            let sum: i64 = req.params.and_then(|p| p.as_array()).map_or(0, |a| a.iter().filter_map(|v| v.as_i64()).sum());
+            Ok((sum + self.offset).to_json())
+        }
+
+        fn name(&self, req: &JsonRpcRequest, _: &()) -> Result<Json, ErrorJsonRpc> {
+            Ok(req.method.to_json())
+        }
+    }
+
+    method_enum! {
+        enum CalculatorMethod for Calculator, Context = () {
+            Add = "add" => add,
+            Name = "calc.name" => name,
+        }
+    }
+
+    #[test]
+    fn test_method_enum() {
+        assert_eq!(Some(CalculatorMethod::Name), CalculatorMethod::from_name("calc.name"));
+        assert_eq!("add", CalculatorMethod::Add.name());
+        assert_eq!(None, CalculatorMethod::from_name("sub"));
+
+        let server = JsonRpcServer::new_handler(Calculator { offset: 10 });
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"params\": [1, 2], \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 13, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"calc.name\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"calc.name\", \
+                                                \"id\": 2}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sub\", \"id\": 3}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32601, \
+                                                \"message\": \"Method not found\"}, \"id\": 3}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+    }
+}
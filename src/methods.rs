@@ -0,0 +1,820 @@
+//! Map of methods, the default handler of `JsonRpcServer`.
+use rustc_serialize::json::{Json, ToJson};
+use std::borrow::Cow;
+use std::collections::hash_map::{Entry, IterMut};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use {matches_shape, validate_json_schema, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest, Method,
+     StreamingHandlerResult};
+
+/**
+ * Method returning outcome other than plain result (see `HashMapWithMethods::insert_outcome`).
+ * */
+type OutcomeMethod = Arc<dyn Fn(&JsonRpcRequest) -> Result<HandlerResult, ErrorJsonRpc> + 'static + Sync + Send>;
+
+/**
+ * Method pushing its result into sink (see `HashMapWithMethods::insert_streaming`).
+ * */
+type StreamingMethod = Arc<dyn Fn(&JsonRpcRequest, &mut StreamingHandlerResult) -> Result<(), ErrorJsonRpc>
+                           + 'static + Sync + Send>;
+
+/**
+ * Handler dispatching requests to methods registered under their names.
+ * Dereferences to underlying `HashMap` for lookups and iteration. Changes go through
+ * its own methods, so flags of methods (eg. deprecation) never outlive them.
+ * */
+#[derive(Default)]
+pub struct HashMapWithMethods {
+    methods: HashMap<String, Method>,
+    deprecated: HashMap<String, Deprecation>,
+    notifications: HashSet<String>,
+    response_required: HashSet<String>,
+    outcomes: HashMap<String, OutcomeMethod>,
+    streaming: HashMap<String, StreamingMethod>,
+}
+
+/**
+ * Notice attached to calls of deprecated method.
+ * */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /**
+     * Version in which method was deprecated.
+     * */
+    pub since: String,
+
+    /**
+     * Method which should be used instead.
+     * */
+    pub replacement: String,
+}
+
+impl ToJson for Deprecation {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        d.insert("since".to_owned(), self.since.to_json());
+        d.insert("use".to_owned(), self.replacement.to_json());
+        Json::Object(d)
+    }
+}
+
+/**
+ * Error returned by `HashMapWithMethods::try_insert` when method is already registered.
+ * */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMethod(pub String);
+
+/**
+ * How `HashMapWithMethods::merge` resolves methods registered in both maps.
+ * */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /**
+     * Use method from merged map.
+     * */
+    Overwrite,
+    /**
+     * Keep existing method.
+     * */
+    Keep,
+    /**
+     * Fail without changing anything.
+     * */
+    Error,
+}
+
+impl fmt::Display for DuplicateMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "method '{}' is already registered", self.0)
+    }
+}
+
+impl Error for DuplicateMethod {}
+
+impl HashMapWithMethods {
+    /**
+     * Create empty map.
+     * */
+    pub fn new() -> HashMapWithMethods {
+        Default::default()
+    }
+
+    /**
+     * Create empty map with room for at least `capacity` methods.
+     * */
+    pub fn with_capacity(capacity: usize) -> HashMapWithMethods {
+        HashMapWithMethods {
+            methods: HashMap::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /**
+     * Register method, replacing (with warning) previous one with the same name.
+     * */
+    pub fn insert(&mut self, name: String, method: Method) -> Option<Method> {
+        if self.methods.contains_key(&name) {
+            warn!("Method '{}' is already registered, overwriting", name);
+        }
+        self.forget(&name);
+        self.methods.insert(name, method)
+    }
+
+    /**
+     * Unregister method together with its flags.
+     * */
+    pub fn remove(&mut self, name: &str) -> Option<Method> {
+        self.forget(name);
+        self.methods.remove(name)
+    }
+
+    /**
+     * Keep only methods for which `keep` returns true, unregistering others together with
+     * their flags.
+     * */
+    pub fn retain<F: FnMut(&String, &mut Method) -> bool>(&mut self, mut keep: F) {
+        let mut removed = Vec::new();
+        self.methods.retain(|name, method| {
+            let kept = keep(name, method);
+            if !kept {
+                removed.push(name.clone());
+            }
+            kept
+        });
+        for name in removed {
+            self.forget(&name);
+        }
+    }
+
+    /**
+     * Unregister all methods together with their flags.
+     * */
+    pub fn clear(&mut self) {
+        *self = HashMapWithMethods::new();
+    }
+
+    /**
+     * Mutable reference to method, eg. to replace it in place. Outcome or streaming variant
+     * registered under the name is dropped, so server calls method as changed.
+     * */
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Method> {
+        self.unshadow(name);
+        self.methods.get_mut(name)
+    }
+
+    /**
+     * Entry of method for in-place manipulation. Outcome or streaming variant registered under
+     * the name is dropped, as with `get_mut`.
+     * */
+    pub fn entry(&mut self, name: String) -> Entry<'_, String, Method> {
+        self.unshadow(&name);
+        self.methods.entry(name)
+    }
+
+    /**
+     * Iterate over methods, allowing to change them. Outcome and streaming variants are dropped,
+     * as with `get_mut`.
+     * */
+    pub fn iter_mut(&mut self) -> IterMut<'_, String, Method> {
+        self.outcomes.clear();
+        self.streaming.clear();
+        self.methods.iter_mut()
+    }
+
+    /**
+     * Drop variants of method called by server instead of plain one, before plain one is changed.
+     * */
+    fn unshadow(&mut self, name: &str) {
+        self.outcomes.remove(name);
+        self.streaming.remove(name);
+    }
+
+    /**
+     * Drop flags of method, before it is replaced or removed.
+     * */
+    fn forget(&mut self, name: &str) {
+        self.deprecated.remove(name);
+        self.notifications.remove(name);
+        self.response_required.remove(name);
+        self.unshadow(name);
+    }
+
+    /**
+     * Find method to call, warning about deprecated ones.
+     * */
+    fn lookup(&self, name: &str) -> Result<&Method, ErrorJsonRpc> {
+        let method = self.get(name).ok_or_else(|| ErrorJsonRpc::new(ErrorCode::MethodNotFound))?;
+        if let Some(deprecation) = self.deprecated.get(name) {
+            warn!("Deprecated method '{}' called, use '{}' instead", name, deprecation.replacement);
+        }
+        Ok(method)
+    }
+
+    /**
+     * Register method returning `HandlerResult`, eg. `ack()`. Called through `Deref` (or by
+     * handlers unaware of outcomes) it returns plain result.
+     * */
+    pub fn insert_outcome<F>(&mut self, name: String, method: F) -> Option<Method>
+        where F: Fn(&JsonRpcRequest) -> Result<HandlerResult, ErrorJsonRpc> + 'static + Sync + Send
+    {
+        let method: OutcomeMethod = Arc::new(method);
+        let plain = method.clone();
+        let previous = self.insert(name.clone(), Box::new(move |req| plain(req).map(HandlerResult::into_json)));
+        self.outcomes.insert(name, method);
+        previous
+    }
+
+    /**
+     * Register method meant to be called as notification. Its result is never sent back:
+     * non-null result is logged as bug in method and no response is sent, even if called with id.
+     * */
+    pub fn insert_notification(&mut self, name: String, method: Method) -> Option<Method> {
+        let previous = self.insert(name.clone(), method);
+        self.notifications.insert(name);
+        previous
+    }
+
+    /**
+     * Register method which must not be called as notification, because its result matters.
+     * Such calls are logged and ignored.
+     * */
+    pub fn insert_requiring_response(&mut self, name: String, method: Method) -> Option<Method> {
+        let previous = self.insert(name.clone(), method);
+        self.response_required.insert(name);
+        previous
+    }
+
+    /**
+     * Register deprecated method. Calls still work, but are logged and, if server is
+     * configured so, responses carry deprecation notice.
+     * */
+    pub fn insert_deprecated(&mut self, name: String, since: &str, replacement: &str, method: Method) -> Option<Method> {
+        let previous = self.insert(name.clone(), method);
+        self.deprecated.insert(name,
+                               Deprecation {
+                                   since: since.to_owned(),
+                                   replacement: replacement.to_owned(),
+                               });
+        previous
+    }
+
+    /**
+     * Register method unless method with the same name already exists.
+     * */
+    pub fn try_insert(&mut self, name: String, method: Method) -> Result<(), DuplicateMethod> {
+        if self.methods.contains_key(&name) {
+            return Err(DuplicateMethod(name));
+        }
+        self.forget(&name);
+        self.methods.insert(name, method);
+        Ok(())
+    }
+
+    /**
+     * Register method receiving whole request object (eg. to read vendor extension members).
+     * Standard members are validated before method is called.
+     * */
+    pub fn insert_raw<F>(&mut self, name: String, method: F) -> Option<Method>
+        where F: Fn(&Json) -> Result<Json, ErrorJsonRpc> + 'static + Sync + Send
+    {
+        self.insert(name,
+                    Box::new(move |req| match req.raw() {
+                        Some(raw) => method(raw),
+                        None => {
+                            error!("Raw request of '{}' is not available", req.method);
+                            Err(ErrorJsonRpc::new(ErrorCode::InternalError))
+                        }
+                    }))
+    }
+
+    /**
+     * Register stateful method which may mutate captured state. Method is guarded by mutex,
+     * so its calls are serialized: concurrent requests wait for each other. If method panics,
+     * later calls are answered with Internal error.
+     * */
+    pub fn insert_mut<F>(&mut self, name: String, method: F) -> Option<Method>
+        where F: FnMut(&JsonRpcRequest) -> Result<Json, ErrorJsonRpc> + 'static + Send
+    {
+        let method = Mutex::new(method);
+        self.insert(name,
+                    Box::new(move |req| match method.lock() {
+                        Ok(mut method) => (*method)(req),
+                        Err(_) => {
+                            error!("Method '{}' panicked before", req.method);
+                            Err(ErrorJsonRpc::new(ErrorCode::InternalError))
+                        }
+                    }))
+    }
+
+    /**
+     * Register method producing array result incrementally: instead of returning it, method
+     * pushes elements into sink. Called through `JsonRpcServer::handle_request_streaming` each
+     * element is written to transport as soon as it is pushed. Other callers (eg. batches) get
+     * elements collected into array, dropped if method fails.
+     * */
+    pub fn insert_streaming<F>(&mut self, name: String, method: F) -> Option<Method>
+        where F: Fn(&JsonRpcRequest, &mut StreamingHandlerResult) -> Result<(), ErrorJsonRpc> + 'static + Sync + Send
+    {
+        let method: StreamingMethod = Arc::new(method);
+        let collected = method.clone();
+        let collect = move |req: &JsonRpcRequest| {
+            let mut chunks = Vec::new();
+            collected(req, &mut StreamingHandlerResult::new(&mut |chunk| chunks.push(chunk)))?;
+            Ok(Json::Array(chunks))
+        };
+        let previous = self.insert(name.clone(), Box::new(collect));
+        self.streaming.insert(name, method);
+        previous
+    }
+
+    /**
+     * Register method whose results are checked against shape specification (see `matches_shape`).
+     * Result not matching it is a bug in method and is reported to client as Internal error.
+     * */
+    pub fn insert_with_output_schema(&mut self, name: String, out_spec: Json, method: Method) -> Option<Method> {
+        self.insert(name,
+                    Box::new(move |req| {
+                        let result = method(req)?;
+                        if !matches_shape(&result, &out_spec) {
+                            error!("Result of '{}' does not match output schema: {}", req.method, result);
+                            return Err(ErrorJsonRpc::new(ErrorCode::InternalError));
+                        }
+                        Ok(result)
+                    }))
+    }
+
+    /**
+     * Register method whose params are validated against JSON Schema (subset supported by
+     * `validate_json_schema`) before it is called. Invalid params are answered with Invalid params
+     * error listing violations in `data`. Absent params are validated as empty object.
+     * */
+    pub fn insert_with_json_schema(&mut self, name: String, schema: Json, method: Method) -> Option<Method> {
+        let empty = Json::Object(BTreeMap::new());
+        self.insert(name,
+                    Box::new(move |req| {
+                        let violations = validate_json_schema(req.params.unwrap_or(&empty), &schema);
+                        if !violations.is_empty() {
+                            return Err(ErrorJsonRpc::new_data(ErrorCode::InvalidParams, violations.to_json()));
+                        }
+                        method(req)
+                    }))
+    }
+
+    /**
+     * Register method whose missing named params are filled with defaults before it is called.
+     * Absent params are treated as empty object, positional params are passed unchanged.
+     * */
+    pub fn insert_with_defaults(&mut self, name: String, defaults: BTreeMap<String, Json>, method: Method)
+                                -> Option<Method> {
+        self.insert(name,
+                    Box::new(move |req| {
+                        let mut params = match req.params {
+                            Some(Json::Object(params)) => params.clone(),
+                            None => BTreeMap::new(),
+                            Some(_) => return method(req),
+                        };
+                        for (key, value) in &defaults {
+                            params.entry(key.clone()).or_insert_with(|| value.clone());
+                        }
+                        call_with_params(&method, req, &Json::Object(params))
+                    }))
+    }
+
+    /**
+     * Register method whose missing trailing positional params are filled with defaults
+     * (default at index `i` is used for param `i`). Absent params are treated as empty array,
+     * named params are passed unchanged.
+     * */
+    pub fn insert_with_positional_defaults(&mut self, name: String, defaults: Vec<Json>, method: Method)
+                                           -> Option<Method> {
+        self.insert(name,
+                    Box::new(move |req| {
+                        let mut params = match req.params {
+                            Some(Json::Array(params)) => params.clone(),
+                            None => Vec::new(),
+                            Some(_) => return method(req),
+                        };
+                        let given = params.len();
+                        params.extend(defaults.iter().skip(given).cloned());
+                        call_with_params(&method, req, &Json::Array(params))
+                    }))
+    }
+
+    /**
+     * Insert all methods of other map (together with their deprecation notices,
+     * notification-only and response required flags).
+     * With `MergePolicy::Error` first conflicting name is returned and map is left intact.
+     * */
+    pub fn merge(&mut self, other: HashMapWithMethods, policy: MergePolicy) -> Result<(), DuplicateMethod> {
+        if policy == MergePolicy::Error {
+            if let Some(name) = other.methods.keys().find(|name| self.methods.contains_key(*name)) {
+                return Err(DuplicateMethod(name.clone()));
+            }
+        }
+        let HashMapWithMethods {
+            methods,
+            mut deprecated,
+            notifications,
+            response_required,
+            mut outcomes,
+            mut streaming,
+        } = other;
+        for (name, method) in methods {
+            if policy == MergePolicy::Keep && self.methods.contains_key(&name) {
+                continue;
+            }
+            let notification = notifications.get(&name).cloned();
+            let requiring_response = response_required.get(&name).cloned();
+            let outcome = outcomes.remove(&name).map(|outcome| (name.clone(), outcome));
+            let streamed = streaming.remove(&name).map(|method| (name.clone(), method));
+            match deprecated.remove(&name) {
+                Some(deprecation) => {
+                    self.insert_deprecated(name, &deprecation.since, &deprecation.replacement, method);
+                }
+                None => {
+                    self.insert(name, method);
+                }
+            }
+            if let Some(name) = notification {
+                self.notifications.insert(name);
+            }
+            if let Some(name) = requiring_response {
+                self.response_required.insert(name);
+            }
+            if let Some((name, outcome)) = outcome {
+                self.outcomes.insert(name, outcome);
+            }
+            if let Some((name, method)) = streamed {
+                self.streaming.insert(name, method);
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Swap whole set of methods in one step (eg. on configuration reload), returning previous one.
+     * */
+    pub fn replace_methods(&mut self, methods: HashMapWithMethods) -> HashMapWithMethods {
+        mem::replace(self, methods)
+    }
+}
+
+impl Extend<(String, Method)> for HashMapWithMethods {
+    /**
+     * Register all methods, as with `insert`.
+     * */
+    fn extend<I: IntoIterator<Item = (String, Method)>>(&mut self, methods: I) {
+        for (name, method) in methods {
+            self.insert(name, method);
+        }
+    }
+}
+
+impl Deref for HashMapWithMethods {
+    type Target = HashMap<String, Method>;
+
+    fn deref(&self) -> &HashMap<String, Method> {
+        &self.methods
+    }
+}
+
+/**
+ * Call method with request whose params are replaced.
+ * */
+fn call_with_params(method: &Method, req: &JsonRpcRequest, params: &Json) -> Result<Json, ErrorJsonRpc> {
+    let request = JsonRpcRequest {
+        method: Cow::Borrowed(&req.method),
+        params: Some(params),
+        id: req.id,
+        raw: req.raw,
+    };
+    method(&request)
+}
+
+impl Handler for HashMapWithMethods {
+    type Context = ();
+    fn handle(&self, req: &JsonRpcRequest, custom: &Self::Context) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, _: &Self::Context) -> Result<HandlerResult, ErrorJsonRpc> {
+        let method = self.lookup(&req.method)?;
+        match self.outcomes.get(&*req.method) {
+            Some(outcome) => outcome(req),
+            None => method(req).map(HandlerResult::Value),
+        }
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &Self::Context,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        match self.streaming.get(&*req.method) {
+            Some(method) => {
+                self.lookup(&req.method)?;
+                method(req, sink).map(|_| HandlerResult::Streamed)
+            }
+            None => self.handle_outcome(req, custom),
+        }
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        self.deprecated.get(method)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.methods.contains_key(method)
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.notifications.contains(method)
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.response_required.contains(method)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::ToJson;
+    use JsonRpcServer;
+
+    #[test]
+    fn test_insert_overwrites() {
+        let mut methods = HashMapWithMethods::new();
+        assert!(methods.insert("value".to_owned(), Box::new(|_| Ok(1.to_json()))).is_none());
+        assert!(methods.insert("value".to_owned(), Box::new(|_| Ok(2.to_json()))).is_some());
+
+        let server = JsonRpcServer::new_handler(methods);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"value\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_try_insert_rejects_duplicate() {
+        let mut methods = HashMapWithMethods::new();
+        assert_eq!(Ok(()), methods.try_insert("value".to_owned(), Box::new(|_| Ok(1.to_json()))));
+        assert_eq!(Err(DuplicateMethod("value".to_owned())),
+                   methods.try_insert("value".to_owned(), Box::new(|_| Ok(2.to_json()))));
+
+        let server = JsonRpcServer::new_handler(methods);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"value\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 1, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_remove_clears_flags() {
+        let mut methods = HashMapWithMethods::new();
+        methods.insert_deprecated("sum".to_owned(), "1.2", "add", Box::new(|_| Ok(3.to_json())));
+        methods.insert_notification("log".to_owned(), Box::new(|_| Ok(Json::Null)));
+        assert!(methods.remove("sum").is_some());
+        assert!(methods.remove("log").is_some());
+        assert!(methods.remove("log").is_none());
+
+        assert_eq!(Ok(()), methods.try_insert("sum".to_owned(), Box::new(|_| Ok(3.to_json()))));
+        assert_eq!(Ok(()), methods.try_insert("log".to_owned(), Box::new(|_| Ok(1.to_json()))));
+        assert!(methods.deprecation("sum").is_none());
+        assert!(!methods.is_notification_only("log"));
+    }
+
+    #[test]
+    fn test_map_api() {
+        let mut methods = HashMapWithMethods::with_capacity(4);
+        methods.extend(vec![("sum".to_owned(), Box::new(|_: &JsonRpcRequest| Ok(3.to_json())) as Method)]);
+        methods.insert_notification("log".to_owned(), Box::new(|_| Ok(Json::Null)));
+        methods.insert_outcome("ping".to_owned(), |_| ::ack());
+        methods.retain(|name, _| name != "log");
+        assert!(!methods.is_notification_only("log"));
+
+        // Changed method is called instead of outcome one
+        *methods.get_mut("ping").unwrap() = Box::new(|_| Ok("pong".to_json()));
+        methods.entry("sum".to_owned()).or_insert_with(|| Box::new(|_| Ok(0.to_json())));
+        let server = JsonRpcServer::new_handler(methods);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"ping\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"pong\", \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 3, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let mut methods = HashMapWithMethods::new();
+        methods.insert_deprecated("sum".to_owned(), "1.2", "add", Box::new(|_| Ok(3.to_json())));
+        methods.clear();
+        assert!(methods.is_empty());
+        methods.insert("sum".to_owned(), Box::new(|_| Ok(3.to_json())));
+        assert!(methods.deprecation("sum").is_none());
+    }
+
+    #[test]
+    fn test_replace_methods() {
+        let mut methods = HashMapWithMethods::new();
+        methods.insert("old".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let mut server = JsonRpcServer::new_handler(methods);
+
+        let mut methods = HashMapWithMethods::new();
+        methods.insert("new".to_owned(), Box::new(|_| Ok(2.to_json())));
+        let old = server.get_handler_mut().replace_methods(methods);
+        assert_eq!(vec!["old"], old.keys().collect::<Vec<_>>());
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"new\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"old\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32601, \"message\": \"Method not found\"}, \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_deprecated_method() {
+        let mut methods = HashMapWithMethods::new();
+        methods.insert("add".to_owned(), Box::new(|_| Ok(3.to_json())));
+        methods.insert_deprecated("sum".to_owned(), "1.2", "add", Box::new(|_| Ok(3.to_json())));
+        let mut server = JsonRpcServer::new_handler(methods);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"sum\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 3, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        server.set_report_deprecation(true);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 3, \"id\": 1, \
+                                                \"_deprecation\": {\"since\": \"1.2\", \"use\": \"add\"}}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"add\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 3, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_output_schema() {
+        let spec = Json::from_str("{\"total\": \"number\"}").unwrap();
+        let mut methods = HashMapWithMethods::new();
+        methods.insert_with_output_schema("good".to_owned(), spec.clone(),
+                                          Box::new(|_| Ok(Json::from_str("{\"total\": 3}").unwrap())));
+        methods.insert_with_output_schema("bad".to_owned(), spec, Box::new(|_| Ok("3".to_json())));
+        let server = JsonRpcServer::new_handler(methods);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"good\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": {\"total\": 3}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"bad\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32603, \"message\": \"Internal error\"}, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    fn module(value: i32) -> HashMapWithMethods {
+        let mut methods = HashMapWithMethods::new();
+        methods.insert("shared".to_owned(), Box::new(move |_| Ok(value.to_json())));
+        methods.insert(format!("only{}", value), Box::new(move |_| Ok(value.to_json())));
+        methods
+    }
+
+    fn call(methods: &HashMapWithMethods, method: &str) -> Option<i64> {
+        let request = JsonRpcRequest {
+            method: method.into(),
+            params: None,
+            id: None,
+            raw: None,
+        };
+        methods.handle(&request, &()).ok().and_then(|r| r.as_i64())
+    }
+
+    #[test]
+    fn test_merge_overwrite() {
+        let mut methods = module(1);
+        assert_eq!(Ok(()), methods.merge(module(2), MergePolicy::Overwrite));
+        assert_eq!(Some(2), call(&methods, "shared"));
+        assert_eq!(Some(1), call(&methods, "only1"));
+        assert_eq!(Some(2), call(&methods, "only2"));
+    }
+
+    #[test]
+    fn test_merge_keep() {
+        let mut methods = module(1);
+        assert_eq!(Ok(()), methods.merge(module(2), MergePolicy::Keep));
+        assert_eq!(Some(1), call(&methods, "shared"));
+        assert_eq!(Some(2), call(&methods, "only2"));
+    }
+
+    #[test]
+    fn test_merge_error() {
+        let mut methods = module(1);
+        assert_eq!(Err(DuplicateMethod("shared".to_owned())), methods.merge(module(2), MergePolicy::Error));
+        assert_eq!(Some(1), call(&methods, "shared"));
+        assert_eq!(None, call(&methods, "only2"));
+    }
+
+    #[test]
+    fn test_insert_with_defaults() {
+        let mut methods = HashMapWithMethods::new();
+        let mut defaults = BTreeMap::new();
+        defaults.insert("limit".to_owned(), 10.to_json());
+        methods.insert_with_defaults("list".to_owned(),
+                                     defaults,
+                                     Box::new(|req| req.param_named_as_i64("limit").map(|l| l.to_json())));
+        methods.insert_with_positional_defaults("page".to_owned(),
+                                                vec![1.to_json(), 20.to_json()],
+                                                Box::new(|req| req.param_at_as_i64(1).map(|l| l.to_json())));
+        let server = JsonRpcServer::new_handler(methods);
+        for &(request, result) in &[("{\"jsonrpc\": \"2.0\", \"method\": \"list\", \"id\": 1}", 10),
+                                    ("{\"jsonrpc\": \"2.0\", \"method\": \"list\", \"params\": {}, \"id\": 1}", 10),
+                                    ("{\"jsonrpc\": \"2.0\", \"method\": \"list\", \"params\": {\"limit\": 5}, \
+                                      \"id\": 1}", 5),
+                                    ("{\"jsonrpc\": \"2.0\", \"method\": \"page\", \"params\": [3], \"id\": 1}", 20),
+                                    ("{\"jsonrpc\": \"2.0\", \"method\": \"page\", \"params\": [3, 50], \"id\": 1}", 50)] {
+            let expected_response = Json::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"result\": {}, \"id\": 1}}",
+                                                            result));
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()), "{}", request);
+        }
+    }
+
+    #[test]
+    fn test_insert_mut() {
+        let mut methods = HashMapWithMethods::new();
+        let mut counter = 0;
+        methods.insert_mut("next".to_owned(), move |_| {
+            counter += 1;
+            Ok(counter.to_json())
+        });
+        let server = JsonRpcServer::new_handler(methods);
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"next\", \"id\": 1}";
+        for expected in 1..4 {
+            let expected_response = Json::from_str(&format!("{{\"jsonrpc\": \"2.0\", \"result\": {}, \"id\": 1}}",
+                                                            expected));
+            assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = Json::from_str("{\"type\": \"object\", \"required\": [\"a\"], \
+                                     \"properties\": {\"a\": {\"type\": \"integer\"}}}")
+                         .unwrap();
+        let mut methods = HashMapWithMethods::new();
+        methods.insert_with_json_schema("inc".to_owned(), schema, Box::new(|req| {
+            Ok((req.params.and_then(|p| p.find("a")).and_then(Json::as_i64).unwrap() + 1).to_json())
+        }));
+        let server = ::JsonRpcServer::new_handler(methods);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"inc\", \"params\": {\"a\": 1}, \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 2, \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"inc\", \"params\": {\"a\": \"1\"}, \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": -32602, \
+                                                \"message\": \"Invalid params\", \"data\": [\"/a: expected integer\"]}, \
+                                                \"id\": 2}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"inc\", \"id\": 3}";
+        let response = server.handle_request(request).unwrap();
+        assert!(response.contains("missing required property 'a'"), "{}", response);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut methods = HashMapWithMethods::new();
+        methods.insert_streaming("rows".to_owned(), |_, sink| {
+            for i in 0..3 {
+                sink.push(i.to_json());
+            }
+            Ok(())
+        });
+        methods.insert_streaming("broken".to_owned(), |_, sink| {
+            sink.push(0.to_json());
+            Err(ErrorJsonRpc::new(ErrorCode::InternalError))
+        });
+        let server = ::JsonRpcServer::new_handler(methods);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"rows\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": [0, 1, 2], \"id\": 1}");
+        assert_eq!(expected_response, Json::from_str(&server.handle_request(request).unwrap()));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"broken\", \"id\": 2}";
+        assert!(server.handle_request(request).unwrap().contains("-32603"));
+    }
+}
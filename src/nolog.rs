@@ -0,0 +1,32 @@
+//! No-op replacements for `log` macros used when `logging` feature is disabled.
+//! Arguments are type checked, but never evaluated.
+
+macro_rules! error {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_arguments_not_evaluated() {
+        let mut evaluated = false;
+        error!("{}", { evaluated = true; 1 });
+        warn!("{}", { evaluated = true; 2 });
+        info!("{}", { evaluated = true; 3 });
+        debug!("{}", { evaluated = true; 4 });
+        assert!(!evaluated);
+    }
+}
@@ -0,0 +1,105 @@
+//! Limiting how often methods can be called.
+use rustc_serialize::json::{Json, ToJson};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use {ErrorCode, ErrorJsonRpc};
+
+/**
+ * Check performed before dispatching each request. Context is available, so limits
+ * can be applied per client.
+ * */
+pub trait RateLimiter<C> {
+    /**
+     * Record call of method. Returns time after which call could succeed when limit is exceeded.
+     * */
+    fn check(&self, method: &str, context: &C) -> Result<(), Duration>;
+}
+
+/**
+ * Build error returned for rate limited request.
+ * */
+pub fn rate_limited(retry_after: Duration) -> ErrorJsonRpc {
+    let mut data = BTreeMap::new();
+    data.insert("retryAfterMs".to_owned(), (retry_after.as_millis() as u64).to_json());
+    ErrorJsonRpc::new_data(ErrorCode::ServerError(-32029, "rate limited"), Json::Object(data))
+}
+
+/**
+ * Limiter allowing given number of calls of method in fixed time windows, shared by all clients.
+ * Methods without configured limit are not limited.
+ * */
+#[derive(Default)]
+pub struct MethodRateLimiter {
+    limits: HashMap<String, (u32, Duration)>,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl MethodRateLimiter {
+    /**
+     * Create limiter without limits.
+     * */
+    pub fn new() -> MethodRateLimiter {
+        Default::default()
+    }
+
+    /**
+     * Allow at most `calls` calls of method per `window`.
+     * */
+    pub fn limit(mut self, method: &str, calls: u32, window: Duration) -> MethodRateLimiter {
+        self.limits.insert(method.to_owned(), (calls, window));
+        self
+    }
+}
+
+impl<C> RateLimiter<C> for MethodRateLimiter {
+    fn check(&self, method: &str, _: &C) -> Result<(), Duration> {
+        let (calls, window) = match self.limits.get(method) {
+            Some(&limit) => limit,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let entry = windows.entry(method.to_owned()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= window {
+            *entry = (now, 0);
+        }
+        if entry.1 >= calls {
+            return Err(window - now.duration_since(entry.0));
+        }
+        entry.1 += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use JsonRpcServer;
+
+    #[test]
+    fn test_rate_limited() {
+        let mut server = JsonRpcServer::new();
+        server.get_handler_mut().insert("expensive".to_owned(), Box::new(|_| Ok(Json::Null)));
+        server.get_handler_mut().insert("cheap".to_owned(), Box::new(|_| Ok(Json::Null)));
+        server.set_rate_limiter(Box::new(MethodRateLimiter::new().limit("expensive", 1, Duration::from_secs(60))));
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"expensive\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": null, \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let response = Json::from_str(&server.handle_request(request).unwrap()).unwrap();
+        let error = response.find("error").unwrap();
+        assert_eq!(Some(-32029), error.find("code").and_then(Json::as_i64));
+        let retry_after = error.find_path(&["data", "retryAfterMs"]).and_then(Json::as_u64).unwrap();
+        assert!(retry_after > 0 && retry_after <= 60000);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"cheap\", \"id\": 1}";
+        for _ in 0..2 {
+            let response = Json::from_str(&server.handle_request(request).unwrap());
+            assert_eq!(expected_response, response);
+        }
+    }
+}
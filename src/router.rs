@@ -0,0 +1,171 @@
+//! Dispatching requests to handlers based on method namespace.
+use rustc_serialize::json::Json;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use {BoxedHandler, Deprecation, ErrorCode, ErrorJsonRpc, Handler, HandlerResult, JsonRpcRequest,
+     StreamingHandlerResult};
+
+/**
+ * Handler dispatching methods like `user.create` to handler mounted for namespace `user`.
+ * Namespace is the part of method name before first dot.
+ * */
+pub struct NamespaceRouter<C> {
+    namespaces: HashMap<String, BoxedHandler<C>>,
+    strip_namespace: bool,
+}
+
+impl<C> NamespaceRouter<C> {
+    /**
+     * Create router without namespaces. Mounted handlers see method names without namespace.
+     * */
+    pub fn new() -> NamespaceRouter<C> {
+        NamespaceRouter {
+            namespaces: HashMap::new(),
+            strip_namespace: true,
+        }
+    }
+
+    /**
+     * Mount handler for namespace, replacing previous one.
+     * */
+    pub fn mount<H>(mut self, namespace: &str, handler: H) -> NamespaceRouter<C>
+        where H: Handler<Context = C> + Sync + Send + 'static
+    {
+        self.namespaces.insert(namespace.to_owned(), Box::new(handler));
+        self
+    }
+
+    /**
+     * Choose if mounted handlers see method name with namespace stripped (default) or intact.
+     * */
+    pub fn strip_namespace(mut self, strip: bool) -> NamespaceRouter<C> {
+        self.strip_namespace = strip;
+        self
+    }
+}
+
+impl<C> Default for NamespaceRouter<C> {
+    fn default() -> NamespaceRouter<C> {
+        NamespaceRouter::new()
+    }
+}
+
+impl<C> NamespaceRouter<C> {
+    /**
+     * Handler mounted for namespace of method and name of method it should see.
+     * */
+    fn route<'a>(&self, method: &'a str) -> Option<(&BoxedHandler<C>, &'a str)> {
+        let (namespace, name) = method.split_once('.')?;
+        let handler = self.namespaces.get(namespace)?;
+        Some((handler, if self.strip_namespace { name } else { method }))
+    }
+
+    /**
+     * Handler mounted for namespace of request and request as it should see it.
+     * */
+    fn routed<'r>(&self, req: &'r JsonRpcRequest) -> Result<(&BoxedHandler<C>, JsonRpcRequest<'r>), ErrorJsonRpc> {
+        let (handler, method) = self.route(&req.method).ok_or_else(|| {
+            error!("No namespace for method '{}'", req.method);
+            ErrorJsonRpc::new(ErrorCode::MethodNotFound)
+        })?;
+        let request = JsonRpcRequest {
+            method: Cow::Borrowed(method),
+            params: req.params,
+            id: req.id,
+            raw: req.raw,
+        };
+        Ok((handler, request))
+    }
+}
+
+impl<C> Handler for NamespaceRouter<C> {
+    type Context = C;
+    fn handle(&self, req: &JsonRpcRequest, custom: &C) -> Result<Json, ErrorJsonRpc> {
+        self.handle_outcome(req, custom).map(HandlerResult::into_json)
+    }
+
+    fn handle_outcome(&self, req: &JsonRpcRequest, custom: &C) -> Result<HandlerResult, ErrorJsonRpc> {
+        let (handler, request) = self.routed(req)?;
+        handler.handle_outcome(&request, custom)
+    }
+
+    fn handle_streaming(&self,
+                        req: &JsonRpcRequest,
+                        custom: &C,
+                        sink: &mut StreamingHandlerResult)
+                        -> Result<HandlerResult, ErrorJsonRpc> {
+        let (handler, request) = self.routed(req)?;
+        handler.handle_streaming(&request, custom, sink)
+    }
+
+    fn deprecation(&self, method: &str) -> Option<&Deprecation> {
+        let (handler, name) = self.route(method)?;
+        handler.deprecation(name)
+    }
+
+    fn has_method(&self, method: &str) -> bool {
+        self.route(method).is_some_and(|(handler, name)| handler.has_method(name))
+    }
+
+    fn is_notification_only(&self, method: &str) -> bool {
+        self.route(method).is_some_and(|(handler, name)| handler.is_notification_only(name))
+    }
+
+    fn requires_response(&self, method: &str) -> bool {
+        self.route(method).is_some_and(|(handler, name)| handler.requires_response(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+    use {HashMapWithMethods, JsonRpcServer};
+
+    fn router() -> NamespaceRouter<()> {
+        let mut user = HashMapWithMethods::new();
+        user.insert("create".to_owned(), Box::new(|req| Ok(req.method.to_json())));
+        let mut billing = HashMapWithMethods::new();
+        billing.insert("charge".to_owned(), Box::new(|_| Ok(100.to_json())));
+        billing.insert("billing.charge".to_owned(), Box::new(|_| Ok(200.to_json())));
+        NamespaceRouter::new().mount("user", user).mount("billing", billing)
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let server = JsonRpcServer::new_handler(router());
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"user.create\", \"id\": 1}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": \"create\", \
+                                                \"id\": 1}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"billing.charge\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 100, \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+
+    #[test]
+    fn test_unknown_namespace() {
+        let server = JsonRpcServer::new_handler(router());
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32601, \"message\": \"Method not found\"}, \
+                                                \"id\": 1}");
+        for method in &["admin.create", "create", "user"] {
+            let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"{}\", \"id\": 1}}", method);
+            let response = Json::from_str(&server.handle_request(&request).unwrap());
+            assert_eq!(expected_response, response);
+        }
+    }
+
+    #[test]
+    fn test_keep_namespace() {
+        let server = JsonRpcServer::new_handler(router().strip_namespace(false));
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"billing.charge\", \"id\": 2}";
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 200, \"id\": 2}");
+        let response = Json::from_str(&server.handle_request(request).unwrap());
+        assert_eq!(expected_response, response);
+    }
+}
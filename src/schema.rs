@@ -0,0 +1,147 @@
+//! Checking shape of JSON values against simple specification.
+use rustc_serialize::json::Json;
+
+/**
+ * Check if value matches shape specification. Specification is JSON value where:
+ * - string names expected type: `"null"`, `"boolean"`, `"number"`, `"string"`, `"array"`,
+ *   `"object"` or `"any"`,
+ * - array with single element requires array with all elements matching that element,
+ * - object requires object containing all listed members, each matching its specification.
+ *
+ * Object members not listed in specification are allowed. Invalid specification never matches.
+ * */
+pub fn matches_shape(value: &Json, spec: &Json) -> bool {
+    match *spec {
+        Json::String(ref name) => match name.as_str() {
+            "any" => true,
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => false,
+        },
+        Json::Array(ref items) if items.len() == 1 => {
+            value.as_array().is_some_and(|values| values.iter().all(|v| matches_shape(v, &items[0])))
+        }
+        Json::Object(ref members) => {
+            value.as_object().is_some_and(|object| {
+                members.iter().all(|(key, spec)| object.get(key).is_some_and(|v| matches_shape(v, spec)))
+            })
+        }
+        _ => false,
+    }
+}
+
+/**
+ * Validate value against subset of JSON Schema: `type` (name or array of names, including
+ * `"integer"`), `required`, `properties` and `items` (single schema). Other keywords are ignored.
+ * Returns violations, each prefixed with JSON pointer of offending value. Empty if value is valid.
+ * */
+pub fn validate_json_schema(value: &Json, schema: &Json) -> Vec<String> {
+    let mut violations = Vec::new();
+    check_schema(value, schema, "", &mut violations);
+    violations
+}
+
+/**
+ * Check if value is of JSON Schema type. Unknown type never matches.
+ * */
+fn is_type(value: &Json, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+fn check_schema(value: &Json, schema: &Json, path: &str, violations: &mut Vec<String>) {
+    // Non-object schema (eg. `true`) accepts anything
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return,
+    };
+    let location = if path.is_empty() { "/" } else { path };
+
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match *expected {
+            Json::String(ref name) => vec![name],
+            Json::Array(ref names) => names.iter().filter_map(Json::as_string).collect(),
+            _ => Vec::new(),
+        };
+        if !names.iter().any(|name| is_type(value, name)) {
+            violations.push(format!("{}: expected {}", location, names.join(" or ")));
+            // Nested keywords make no sense for value of other type
+            return;
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(Json::as_array) {
+            for name in required.iter().filter_map(Json::as_string).filter(|name| !object.contains_key(*name)) {
+                violations.push(format!("{}: missing required property '{}'", location, name));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Json::as_object) {
+            for (name, property) in properties {
+                if let Some(member) = object.get(name) {
+                    let escaped = name.replace('~', "~0").replace('/', "~1");
+                    check_schema(member, property, &format!("{}/{}", path, escaped), violations);
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in values.iter().enumerate() {
+            check_schema(item, items, &format!("{}/{}", path, i), violations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_shape() {
+        let spec = Json::from_str("{\"name\": \"string\", \"tags\": [\"string\"], \"extra\": \"any\"}").unwrap();
+        let valid = Json::from_str("{\"name\": \"x\", \"tags\": [\"a\", \"b\"], \"extra\": null, \
+                                    \"other\": 1}").unwrap();
+        assert!(matches_shape(&valid, &spec));
+
+        for invalid in &["{\"name\": 1, \"tags\": [], \"extra\": 1}",
+                         "{\"name\": \"x\", \"tags\": [1], \"extra\": 1}",
+                         "{\"name\": \"x\", \"tags\": []}",
+                         "[]"] {
+            assert!(!matches_shape(&Json::from_str(invalid).unwrap(), &spec), "{}", invalid);
+        }
+        assert!(!matches_shape(&Json::Null, &Json::from_str("\"nothing\"").unwrap()));
+    }
+
+    #[test]
+    fn test_validate_json_schema() {
+        let schema = Json::from_str("{\"type\": \"object\", \"required\": [\"name\", \"age\"], \"properties\": {\
+                                     \"name\": {\"type\": \"string\"}, \"age\": {\"type\": \"integer\"}, \
+                                     \"tags\": {\"type\": \"array\", \"items\": {\"type\": \"string\"}}, \
+                                     \"note\": {\"type\": [\"string\", \"null\"]}}}")
+                         .unwrap();
+        let valid = Json::from_str("{\"name\": \"x\", \"age\": 3, \"tags\": [\"a\"], \"note\": null, \"other\": 1}")
+                        .unwrap();
+        assert!(validate_json_schema(&valid, &schema).is_empty());
+
+        let invalid = Json::from_str("{\"age\": 3.5, \"tags\": [\"a\", 1], \"note\": 2}").unwrap();
+        assert_eq!(vec!["/: missing required property 'name'".to_owned(),
+                        "/age: expected integer".to_owned(),
+                        "/note: expected string or null".to_owned(),
+                        "/tags/1: expected string".to_owned()],
+                   validate_json_schema(&invalid, &schema));
+        assert_eq!(vec!["/: expected object".to_owned()], validate_json_schema(&Json::Null, &schema));
+    }
+}
@@ -0,0 +1,309 @@
+//! Processing large requests and responses without buffering them as text.
+use rustc_serialize::json::{Builder, Json, ToJson};
+use std::io::{self, BufReader, Bytes, Read};
+use std::str;
+
+use {encoding_error, ErrorCode, ErrorJsonRpc, Handler, InternalErrorCode, JsonRpcServer};
+
+/**
+ * Sink into which streaming method pushes its result element by element (see
+ * `HashMapWithMethods::insert_streaming`). Depending on caller elements are written to
+ * transport right away (`JsonRpcServer::handle_request_streaming`) or collected into array.
+ * */
+pub struct StreamingHandlerResult<'a> {
+    write: &'a mut dyn FnMut(Json),
+}
+
+impl<'a> StreamingHandlerResult<'a> {
+    /**
+     * Create sink passing each pushed element to `write`.
+     * */
+    pub fn new(write: &'a mut dyn FnMut(Json)) -> StreamingHandlerResult<'a> {
+        StreamingHandlerResult { write }
+    }
+
+    /**
+     * Append element to result.
+     * */
+    pub fn push(&mut self, chunk: Json) {
+        (self.write)(chunk);
+    }
+}
+
+/**
+ * Reason why reading characters stopped before end of stream.
+ * */
+enum Failure {
+    TooLarge,
+    Encoding,
+    Io(io::Error),
+}
+
+/**
+ * Iterator decoding UTF-8 characters directly from reader.
+ * Stops on first failure, which is kept for inspection after parsing.
+ * */
+struct Utf8Chars<R> {
+    bytes: Bytes<BufReader<R>>,
+    consumed: usize,
+    limit: Option<usize>,
+    failure: Option<Failure>,
+}
+
+impl<R: Read> Utf8Chars<R> {
+    fn new(reader: R, limit: Option<usize>) -> Utf8Chars<R> {
+        Utf8Chars {
+            bytes: BufReader::new(reader).bytes(),
+            consumed: 0,
+            limit,
+            failure: None,
+        }
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        match self.bytes.next() {
+            Some(Ok(byte)) => {
+                self.consumed += 1;
+                if self.limit.is_some_and(|limit| self.consumed > limit) {
+                    self.failure = Some(Failure::TooLarge);
+                    return None;
+                }
+                Some(byte)
+            }
+            Some(Err(e)) => {
+                self.failure = Some(Failure::Io(e));
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+impl<R: Read> Iterator for Utf8Chars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.failure.is_some() {
+            return None;
+        }
+        let first = self.byte()?;
+        let width = match first {
+            0x00..=0x7F => return Some(first as char),
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 0,
+        };
+        let mut buf = [first, 0, 0, 0];
+        for slot in buf.iter_mut().take(width).skip(1) {
+            match self.byte() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+        }
+        let decoded = str::from_utf8(&buf[..width]).ok().and_then(|s| s.chars().next());
+        if decoded.is_none() && self.failure.is_none() {
+            self.failure = Some(Failure::Encoding);
+        }
+        decoded
+    }
+}
+
+impl<H: Handler> JsonRpcServer<H> {
+    /**
+     * Process UTF-8 encoded request read directly from `reader`.
+     * Request text is never held in memory as a whole, only parsed value is, so peak memory
+     * is lower by the size of request (see `benches/large_request.rs`).
+     * Invalid UTF-8 is answered with Parse error, read failures are returned as `Err`.
+     * */
+    pub fn handle_large_request<R: Read>(&self, reader: R, custom: &H::Context) -> io::Result<Option<String>> {
+        let mut chars = Utf8Chars::new(reader, self.options.max_request_bytes);
+        let parsed = Builder::new(&mut chars).build();
+        let result = match chars.failure {
+            Some(Failure::Io(e)) => return Err(e),
+            Some(Failure::TooLarge) => {
+                error!("Request exceeds limit of {} bytes", chars.limit.unwrap_or_default());
+                Err(InternalErrorCode::WithoutId(ErrorCode::InvalidRequest, None))
+            }
+            Some(Failure::Encoding) => {
+                error!("Request is not valid UTF-8 text");
+                Err(encoding_error("invalid-utf8"))
+            }
+            None => parsed.map_err(InternalErrorCode::from)
+                          .and_then(|request_json| self._handle_parsed(&request_json, custom)),
+        };
+        Ok(self._response_string(result))
+    }
+
+    /**
+     * Process request, passing response to `write` in pieces. Result of streaming method (see
+     * `HashMapWithMethods::insert_streaming`) is written element by element as method pushes
+     * them, so transport may flush them before method returns. Any other response (including
+     * batches) is written in one piece, same as returned by `handle_request_context`.
+     * Streamed response is written compactly and bypasses `max_response_bytes`, response audit
+     * and history, as it is never held as a whole.
+     * Returns whether anything was written. Once first element is written response can't be
+     * replaced by error, so if method fails later, writing stops and error is returned: response
+     * is left truncated and transport should close connection.
+     * */
+    pub fn handle_request_streaming<W>(&self,
+                                       request: &str,
+                                       custom: &H::Context,
+                                       mut write: W)
+                                       -> Result<bool, ErrorJsonRpc>
+        where W: FnMut(&str)
+    {
+        let parsed = self._check_size(request.len()).ok().and_then(|_| Json::from_str(request).ok());
+        let (request_json, id) = match parsed.and_then(|json| json.find("id").cloned().map(|id| (json, id))) {
+            Some(call) => call,
+            // Only result of single call can be streamed
+            None => {
+                let response = self.handle_request_context(request, custom);
+                if let Some(ref response) = response {
+                    write(response);
+                }
+                return Ok(response.is_some());
+            }
+        };
+
+        let mut started = false;
+        let result = {
+            let head = if self.options.omit_version_field {
+                "{\"result\":["
+            } else {
+                "{\"jsonrpc\":\"2.0\",\"result\":["
+            };
+            let mut write_chunk = |chunk: Json| {
+                write(if started { "," } else { head });
+                write(&chunk.to_string());
+                started = true;
+            };
+            self._handle_streamed(&request_json, custom, Some(&mut StreamingHandlerResult::new(&mut write_chunk)))
+        };
+        match result {
+            Ok(_) if started => {
+                write(&format!("],\"id\":{}}}", id));
+                Ok(true)
+            }
+            Err(error) if started => {
+                error!("Streaming result of request {} failed, response is truncated", id);
+                Err(error.into_error())
+            }
+            // Nothing written yet, answer as usual
+            result => {
+                let response = result.unwrap_or_else(InternalErrorCode::into_response).to_json();
+                let response = self._response_string(Ok(Some(response)));
+                self.history.record(request, response.as_ref());
+                if let Some(ref response) = response {
+                    write(response);
+                }
+                Ok(response.is_some())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use HashMapWithMethods;
+
+    fn server() -> JsonRpcServer<HashMapWithMethods> {
+        let mut handler = HashMapWithMethods::new();
+        handler.insert("count".to_owned(),
+                       Box::new(|req| Ok(req.params.and_then(Json::as_array).map_or(0, Vec::len).to_json())));
+        JsonRpcServer::new_handler(handler)
+    }
+
+    #[test]
+    fn test_large_request() {
+        let params = vec!["żółć".to_owned(); 10000].to_json().to_string();
+        let request = format!("{{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"params\": {}, \"id\": 1}}",
+                              params);
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"result\": 10000, \"id\": 1}");
+        let response = server().handle_large_request(request.as_bytes(), &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+    }
+
+    #[test]
+    fn test_large_request_invalid() {
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\"}, \"id\": null}");
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"id\": 1} x";
+        let response = server().handle_large_request(request.as_bytes(), &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32700, \"message\": \"Parse error\", \"data\": \
+                                                {\"reason\": \"invalid-utf8\"}}, \"id\": null}");
+        for request in &[&b"{\"jsonrpc\": \"2.0\", \"method\": \"co\xffnt\", \"id\": 1}"[..],
+                         &b"{\"jsonrpc\": \"2.0\", \"method\": \"co\xc5"[..]] {
+            let response = server().handle_large_request(*request, &()).unwrap();
+            assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+        }
+
+        let mut server = server();
+        server.set_max_request_bytes(Some(16));
+        let expected_response = Json::from_str("{\"jsonrpc\": \"2.0\", \"error\": {\"code\": \
+                                                -32600, \"message\": \"Invalid Request\"}, \
+                                                \"id\": null}");
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"count\", \"id\": 1}";
+        let response = server.handle_large_request(request.as_bytes(), &()).unwrap();
+        assert_eq!(expected_response, Json::from_str(&response.unwrap()));
+    }
+
+    #[test]
+    fn test_streamed_result() {
+        let written = Arc::new(AtomicUsize::new(0));
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let mut handler = HashMapWithMethods::new();
+        let (pieces_written, pushed) = (written.clone(), progress.clone());
+        handler.insert_streaming("rows".to_owned(), move |_, sink| {
+            for i in 0..3 {
+                sink.push(i.to_json());
+                pushed.lock().unwrap().push(pieces_written.load(Ordering::SeqCst));
+            }
+            Ok(())
+        });
+        handler.insert_streaming("broken".to_owned(), |_, sink| {
+            sink.push(0.to_json());
+            Err(ErrorJsonRpc::new(ErrorCode::InternalError))
+        });
+        handler.insert("one".to_owned(), Box::new(|_| Ok(1.to_json())));
+        let server = JsonRpcServer::new_handler(handler);
+        let stream = |request: &str| {
+            let mut pieces = Vec::new();
+            let outcome = server.handle_request_streaming(request, &(), |piece| {
+                written.fetch_add(1, Ordering::SeqCst);
+                pieces.push(piece.to_owned());
+            });
+            (outcome, pieces)
+        };
+
+        let request = "{\"jsonrpc\": \"2.0\", \"method\": \"rows\", \"id\": 1}";
+        let (outcome, pieces) = stream(request);
+        assert!(outcome.unwrap());
+        // Each element reached transport before method returned
+        assert_eq!(vec![2, 4, 6], *progress.lock().unwrap());
+        assert_eq!(vec!["{\"jsonrpc\":\"2.0\",\"result\":[", "0", ",", "1", ",", "2", "],\"id\":1}"], pieces);
+        assert_eq!(Json::from_str(&server.handle_request(request).unwrap()), Json::from_str(&pieces.concat()));
+
+        // Failure after first element can only cut response short
+        let (outcome, pieces) = stream("{\"jsonrpc\": \"2.0\", \"method\": \"broken\", \"id\": 2}");
+        assert_eq!(-32603, outcome.unwrap_err().get_code());
+        assert_eq!(vec!["{\"jsonrpc\":\"2.0\",\"result\":[", "0"], pieces);
+
+        // Other responses are written whole
+        let request = "[{\"jsonrpc\": \"2.0\", \"method\": \"rows\", \"id\": 1}, \
+                       {\"jsonrpc\": \"2.0\", \"method\": \"one\", \"id\": 2}]";
+        let (outcome, pieces) = stream(request);
+        assert!(outcome.unwrap());
+        assert_eq!(vec![server.handle_request(request).unwrap()], pieces);
+        let (outcome, pieces) = stream("{\"jsonrpc\": \"2.0\", \"method\": \"rows\"}");
+        assert!(!outcome.unwrap());
+        assert!(pieces.is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+//! Helpers for testing methods (enabled with `testing` feature).
+use rustc_serialize::json::Json;
+use std::convert::TryFrom;
+
+use {parse_batch_response, Handler, JsonRpcServer, ParsedResponse};
+
+/**
+ * Bring numbers to the form produced by parser, so eg. `19.to_json()` equals parsed `19`.
+ * */
+fn normalize(json: &Json) -> Json {
+    Json::from_str(&json.to_string()).expect("Json always serializes to valid JSON")
+}
+
+/**
+ * Collect human readable differences between two values, identified by their path.
+ * */
+fn diff(path: &str, actual: &Json, expected: &Json, out: &mut Vec<String>) {
+    match (actual, expected) {
+        (Json::Object(a), Json::Object(e)) => {
+            for key in e.keys().chain(a.keys().filter(|k| !e.contains_key(*k))) {
+                let path = format!("{}.{}", path, key);
+                match (a.get(key), e.get(key)) {
+                    (Some(a), Some(e)) => diff(&path, a, e, out),
+                    (None, Some(e)) => out.push(format!("{}: missing, expected {}", path, e)),
+                    (Some(a), None) => out.push(format!("{}: unexpected {}", path, a)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Json::Array(a), Json::Array(e)) if a.len() == e.len() => {
+            for (i, (a, e)) in a.iter().zip(e).enumerate() {
+                diff(&format!("{}[{}]", path, i), a, e, out);
+            }
+        }
+        (a, e) if a != e => out.push(format!("{}: expected {}, got {}", path, e, a)),
+        _ => {}
+    }
+}
+
+/**
+ * Assert two responses are the same JSON, regardless of formatting and member order.
+ * On mismatch panics listing all differences.
+ * */
+pub fn assert_response_eq(actual: &str, expected: &str) {
+    let parse = |what, text: &str| {
+        Json::from_str(text).unwrap_or_else(|e| panic!("Invalid {} response {}: {}", what, text, e))
+    };
+    let mut differences = Vec::new();
+    diff("$", &parse("actual", actual), &parse("expected", expected), &mut differences);
+    if !differences.is_empty() {
+        panic!("Responses differ:\n  {}", differences.join("\n  "));
+    }
+}
+
+/**
+ * Client sending requests directly to wrapped server.
+ * */
+pub struct TestClient<H: Handler + 'static> {
+    server: JsonRpcServer<H>,
+}
+
+impl<H: Handler> TestClient<H> {
+    /**
+     * Wrap server.
+     * */
+    pub fn new(server: JsonRpcServer<H>) -> TestClient<H> {
+        TestClient { server }
+    }
+
+    /**
+     * Get server reference
+     * */
+    pub fn get_server(&self) -> &JsonRpcServer<H> {
+        &self.server
+    }
+
+    /**
+     * Send single request using given context. Panics if server response is not valid
+     * JSON-RPC response.
+     * */
+    pub fn send_context(&self, request: &str, custom: &H::Context) -> Option<ParsedResponse> {
+        self.server.handle_request_context(request, custom).map(|response| {
+            ParsedResponse::try_from(response.as_str())
+                .unwrap_or_else(|e| panic!("Invalid response {}: {}", response, e))
+        })
+    }
+
+    /**
+     * Send batch request using given context. Panics if server response is not valid
+     * JSON-RPC batch response.
+     * */
+    pub fn send_batch_context(&self, request: &str, custom: &H::Context) -> Vec<ParsedResponse> {
+        self.server.handle_request_context(request, custom).map_or_else(Vec::new, |response| {
+            parse_batch_response(&response)
+                .unwrap_or_else(|e| panic!("Invalid batch response {}: {}", response, e))
+        })
+    }
+}
+
+impl<H: Handler> TestClient<H> where H::Context: Default {
+    /**
+     * Send single request using default context.
+     * */
+    pub fn send(&self, request: &str) -> Option<ParsedResponse> {
+        self.send_context(request, &Default::default())
+    }
+
+    /**
+     * Send batch request using default context.
+     * */
+    pub fn send_batch(&self, request: &str) -> Vec<ParsedResponse> {
+        self.send_batch_context(request, &Default::default())
+    }
+}
+
+impl ParsedResponse {
+    /**
+     * Assert response is successful, has given id and result.
+     * */
+    pub fn assert_result(&self, id: &Json, expected: &Json) {
+        assert_eq!(normalize(id), *self.id(), "Unexpected response id");
+        match (self.result(), self.error()) {
+            (Some(result), None) => assert_eq!(normalize(expected), *result, "Unexpected result"),
+            (_, error) => panic!("Expected result {}, got error {:?}", expected, error),
+        }
+    }
+
+    /**
+     * Assert response is an error with given id and code.
+     * */
+    pub fn assert_error(&self, id: &Json, code: i32) {
+        assert_eq!(normalize(id), *self.id(), "Unexpected response id");
+        match self.error() {
+            Some(error) => assert_eq!(code, error.get_code(), "Unexpected error code"),
+            None => panic!("Expected error {}, got result {:?}", code, self.result()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::{Json, ToJson};
+    use tests::subtract_server;
+    use HashMapWithMethods;
+
+    fn client() -> TestClient<HashMapWithMethods> {
+        TestClient::new(subtract_server())
+    }
+
+    #[test]
+    fn test_assert_result() {
+        client().send("{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \"params\": [42, 23], \
+                       \"id\": 1}")
+                .unwrap()
+                .assert_result(&1.to_json(), &19.to_json());
+    }
+
+    #[test]
+    fn test_assert_error() {
+        client().send("{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": \"1\"}")
+                .unwrap()
+                .assert_error(&"1".to_json(), -32601);
+    }
+
+    #[test]
+    fn test_batch_and_notification() {
+        let client = client();
+        assert_eq!(None, client.send("{\"jsonrpc\": \"2.0\", \"method\": \"subtract\"}"));
+        let responses = client.send_batch("[{\"jsonrpc\": \"2.0\", \"method\": \"subtract\", \
+                                           \"id\": 1}, 1]");
+        assert_eq!(2, responses.len());
+        responses[0].assert_result(&1.to_json(), &19.to_json());
+        responses[1].assert_error(&Json::Null, -32600);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected result")]
+    fn test_assert_result_fails_on_error() {
+        client().send("{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 1}")
+                .unwrap()
+                .assert_result(&1.to_json(), &19.to_json());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected error code")]
+    fn test_assert_error_fails_on_other_code() {
+        client().send("{\"jsonrpc\": \"2.0\", \"method\": \"foobar\", \"id\": 1}")
+                .unwrap()
+                .assert_error(&1.to_json(), -32600);
+    }
+
+    #[test]
+    fn test_assert_response_eq() {
+        assert_response_eq("{\"jsonrpc\":\"2.0\",\"result\":[1,2],\"id\":1}",
+                           "{ \"id\": 1,\n  \"jsonrpc\": \"2.0\",\n  \"result\": [1, 2] }");
+    }
+
+    #[test]
+    #[should_panic(expected = "$.id: missing, expected 1\n  $.result[1]: expected 3, got 2")]
+    fn test_assert_response_eq_diff() {
+        assert_response_eq("{\"jsonrpc\": \"2.0\", \"result\": [1, 2]}",
+                           "{\"jsonrpc\": \"2.0\", \"result\": [1, 3], \"id\": 1}");
+    }
+}
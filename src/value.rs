@@ -0,0 +1,74 @@
+//! Minimal abstraction over JSON value used by request dispatch.
+use rustc_serialize::json::Json;
+
+/**
+ * Operations on JSON value required to validate and dispatch request.
+ * Implementing this trait for other JSON representation is first step to support
+ * alternative backends.
+ * */
+pub trait JsonValue {
+    /**
+     * Check if value is an object.
+     * */
+    fn is_object(&self) -> bool;
+
+    /**
+     * Check if value is an array.
+     * */
+    fn is_array(&self) -> bool;
+
+    /**
+     * Check if value is null.
+     * */
+    fn is_null(&self) -> bool;
+
+    /**
+     * Check if value is boolean.
+     * */
+    fn is_boolean(&self) -> bool;
+
+    /**
+     * Get string slice if value is a string.
+     * */
+    fn as_str(&self) -> Option<&str>;
+
+    /**
+     * Get member of object. None if value is not an object or member does not exist.
+     * */
+    fn get(&self, key: &str) -> Option<&Self>;
+
+    /**
+     * Get names of object members. Empty if value is not an object.
+     * */
+    fn keys(&self) -> Vec<&str>;
+}
+
+impl JsonValue for Json {
+    fn is_object(&self) -> bool {
+        Json::is_object(self)
+    }
+
+    fn is_array(&self) -> bool {
+        Json::is_array(self)
+    }
+
+    fn is_null(&self) -> bool {
+        Json::is_null(self)
+    }
+
+    fn is_boolean(&self) -> bool {
+        Json::is_boolean(self)
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        self.as_string()
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        self.as_object().and_then(|o| o.get(key))
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.as_object().map_or_else(Vec::new, |o| o.keys().map(|k| k.as_str()).collect())
+    }
+}